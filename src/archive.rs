@@ -0,0 +1,406 @@
+//! Copies each finished CSV to the `archive_targets` from settings, so the
+//! sentences survive a failed SD card. Copies are verified by checksum and
+//! failed ones are retried, also after a restart. Each copy is named after
+//! the installation and the time it was queued, so kiosks sharing a
+//! destination and files with a reused name never replace an older backup.
+
+use crate::checksum::{sha256, sha256_reader};
+use crate::error::{Error, Result};
+use crate::mdns::{hostname, installation_name};
+use crate::paths::state_dir;
+use crate::store::{load_settings, Store};
+use chrono::Local;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const QUEUE_FILE: &str = "archive-queue.json";
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MIN_RETRY: Duration = Duration::from_secs(30);
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Target {
+    /// A folder on another disk or USB stick
+    Copy { path: PathBuf },
+    /// A folder to write a `.tar.zst` per file to
+    Tar { path: PathBuf },
+    /// Anything rsync accepts, i.e. `user@host:/backups/last-snow/` over SSH
+    Rsync { destination: String },
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Copy { path } => write!(f, "copy to {}", path.display()),
+            Target::Tar { path } => write!(f, "tar in {}", path.display()),
+            Target::Rsync { destination } => write!(f, "rsync to {}", destination),
+        }
+    }
+}
+
+fn file_name(file: &Path) -> Result<&std::ffi::OsStr> {
+    file.file_name()
+        .ok_or_else(|| Error::Archive(format!("{} has no file name", file.display())))
+}
+
+fn verify(expected: &str, actual: &str, copy: &Path) -> Result<()> {
+    if expected == actual {
+        return Ok(());
+    }
+    fs::remove_file(copy).unwrap_or_else(|e| {
+        log::error!("Error removing {}: {}", copy.display(), e);
+    });
+    Err(Error::Archive(format!(
+        "Checksum mismatch for {}",
+        copy.display()
+    )))
+}
+
+/// `<installation>-<file stem>-<time>`, with anything but letters, digits,
+/// `-` and `_` in the installation name replaced.
+fn archive_name(installation: &str, file: &Path) -> String {
+    let installation: String = installation
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '-',
+        })
+        .collect();
+    format!(
+        "{}-{}-{}",
+        installation,
+        file.file_stem().unwrap_or_default().to_string_lossy(),
+        Local::now().format("%Y%m%d-%H%M%S")
+    )
+}
+
+/// `name` with the extension of `file`.
+fn with_extension_of(name: &str, file: &Path) -> String {
+    match file.extension() {
+        Some(ext) => format!("{}.{}", name, ext.to_string_lossy()),
+        None => name.to_string(),
+    }
+}
+
+impl Target {
+    fn archive(&self, file: &Path, name: &str) -> Result<()> {
+        match self {
+            Target::Copy { path } => {
+                fs::create_dir_all(path)?;
+                let dest = path.join(with_extension_of(name, file));
+                if dest.exists() {
+                    // Left by an attempt that finished after the queue was saved
+                    if sha256(&dest)? == sha256(file)? {
+                        return Ok(());
+                    }
+                    return Err(Error::Archive(format!(
+                        "{} already exists with other contents",
+                        dest.display()
+                    )));
+                }
+                // Renamed once verified, so a half copy never looks finished
+                let part = dest.with_extension("part");
+                fs::copy(file, &part)?;
+                File::open(&part)?.sync_all()?;
+                verify(&sha256(file)?, &sha256(&part)?, &part)?;
+                fs::rename(&part, &dest)?;
+            }
+            Target::Tar { path } => {
+                fs::create_dir_all(path)?;
+                let dest = path.join(format!("{}.tar.zst", name));
+                let part = dest.with_extension("part");
+
+                let mut builder = tar::Builder::new(zstd::Encoder::new(File::create(&part)?, 0)?);
+                builder.append_path_with_name(file, file_name(file)?)?;
+                builder.into_inner()?.finish()?.sync_all()?;
+
+                let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(&part)?)?);
+                let archived = match archive.entries()?.next() {
+                    Some(entry) => sha256_reader(entry?)?,
+                    None => String::new(),
+                };
+                verify(&sha256(file)?, &archived, &part)?;
+                fs::rename(&part, &dest)?;
+            }
+            Target::Rsync { destination } => {
+                let separator = if destination.ends_with('/') { "" } else { "/" };
+                let destination = format!(
+                    "{}{}{}",
+                    destination,
+                    separator,
+                    with_extension_of(name, file)
+                );
+                // An existing copy is kept, and reported by the check below if it differs
+                let output = Command::new("rsync")
+                    .args(["--checksum", "--ignore-existing", "--timeout=60"])
+                    .arg(file)
+                    .arg(&destination)
+                    .output()?;
+                if !output.status.success() {
+                    return Err(Error::Archive(format!(
+                        "rsync failed with {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+
+                // A dry run lists the file again if the copy differs from it
+                let output = Command::new("rsync")
+                    .args(["--checksum", "--dry-run", "--itemize-changes"])
+                    .arg(file)
+                    .arg(&destination)
+                    .output()?;
+                if !output.status.success() || !output.stdout.is_empty() {
+                    return Err(Error::Archive(format!(
+                        "Checksum mismatch for {} at {}",
+                        file.display(),
+                        destination
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+struct Job {
+    file: PathBuf,
+    target: Target,
+    /// The name of the copy without extension, see [`archive_name`]
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    attempts: u32,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(skip)]
+    next_attempt: Option<Instant>,
+}
+
+static QUEUE: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
+fn queue_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join(QUEUE_FILE))
+}
+
+fn save(queue: &[Job]) {
+    // Written aside and renamed, so a power cut never leaves half a queue
+    let saved = queue_path().and_then(|path| {
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(queue)?)?;
+        File::open(&tmp_path)?.sync_all()?;
+        Ok(fs::rename(&tmp_path, &path)?)
+    });
+    if let Err(e) = saved {
+        log::error!("Error saving the archive queue: {}", e);
+    }
+}
+
+fn load() -> Vec<Job> {
+    let Ok(path) = queue_path() else {
+        return Vec::new();
+    };
+    if !path.exists() {
+        return Vec::new();
+    }
+    fs::read(&path)
+        .map_err(Error::from)
+        .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+        .unwrap_or_else(|e| {
+            log::error!("Error loading the archive queue: {}", e);
+            Vec::new()
+        })
+}
+
+fn targets(store: &Store) -> Vec<Target> {
+    let Some(targets) = store.get("archive_targets") else {
+        return Vec::new();
+    };
+    serde_json::from_value(targets.clone()).unwrap_or_else(|e| {
+        log::error!("Error in archive_targets: {}", e);
+        Vec::new()
+    })
+}
+
+/// Queues a finished file for every target in settings.
+pub fn enqueue(file: &Path) {
+    let store = match load_settings() {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!("Error queueing {}: {}", file.display(), e);
+            return;
+        }
+    };
+
+    let name = archive_name(&installation_name(&store), file);
+    let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    for target in targets(&store) {
+        log::info!("Queueing {} for {} as {}", file.display(), target, name);
+        queue.push(Job {
+            file: file.to_path_buf(),
+            target,
+            name: name.clone(),
+            attempts: 0,
+            error: None,
+            next_attempt: None,
+        });
+    }
+    save(&queue);
+}
+
+/// `ok`, or the number of copies waiting for a retry and the last error.
+pub fn status() -> String {
+    let queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    let failed: Vec<&Job> = queue.iter().filter(|job| job.attempts > 0).collect();
+    match failed.last() {
+        None => "ok".to_string(),
+        Some(job) => format!(
+            "{} failed, last: {} {}: {}",
+            failed.len(),
+            job.file.display(),
+            job.target,
+            job.error.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+fn run_due_jobs() {
+    let due: Vec<Job> = {
+        let queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+        queue
+            .iter()
+            .filter(|job| !matches!(job.next_attempt, Some(next) if next > Instant::now()))
+            .cloned()
+            .collect()
+    };
+
+    for job in due {
+        // Removed files are not retried forever
+        let result = if job.file.exists() {
+            job.target.archive(&job.file, &job.name).map(|()| true)
+        } else {
+            Ok(false)
+        };
+        let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(index) = queue
+            .iter()
+            .position(|queued| queued.file == job.file && queued.target == job.target)
+        else {
+            continue;
+        };
+        match result {
+            Ok(false) => {
+                log::warn!("Not archiving {}, it no longer exists", job.file.display());
+                queue.remove(index);
+            }
+            Ok(true) => {
+                log::info!("Archived {} ({})", job.file.display(), job.target);
+                queue.remove(index);
+            }
+            Err(e) => {
+                let queued = &mut queue[index];
+                queued.attempts += 1;
+                let retry = std::cmp::min(
+                    MIN_RETRY.saturating_mul(2u32.saturating_pow(queued.attempts - 1)),
+                    MAX_RETRY,
+                );
+                log::error!(
+                    "Error archiving {} ({}), retrying in {:?}: {}",
+                    job.file.display(),
+                    job.target,
+                    retry,
+                    e
+                );
+                queued.error = Some(e.to_string());
+                queued.next_attempt = Some(Instant::now() + retry);
+            }
+        }
+        save(&queue);
+    }
+}
+
+/// Loads the copies left from the last run and archives queued files in a background thread.
+pub fn start() {
+    {
+        let installation = load_settings()
+            .map(|store| installation_name(&store))
+            .unwrap_or_else(|e| {
+                log::error!("Error loading settings: {}", e);
+                hostname()
+            });
+
+        let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+        // Copies queued by older versions have no name yet
+        queue.extend(load().into_iter().map(|mut job| {
+            if job.name.is_empty() {
+                job.name = archive_name(&installation, &job.file);
+            }
+            job
+        }));
+        if !queue.is_empty() {
+            log::info!("{} archive copies left from the last run", queue.len());
+        }
+    }
+
+    thread::spawn(|| loop {
+        run_due_jobs();
+        thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("last-snow-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn names_copies_by_installation() {
+        let name = archive_name("Hall 2/Kiosk", Path::new("/data/sentences/EN.csv"));
+        assert!(name.starts_with("Hall-2-Kiosk-EN-"), "{}", name);
+        assert_eq!(with_extension_of(&name, Path::new("EN.csv")), name + ".csv");
+    }
+
+    #[test]
+    fn keeps_older_copies() {
+        let dir = temp_dir("archive-copy");
+        let file = dir.join("EN.csv");
+        let target = Target::Copy {
+            path: dir.join("backup"),
+        };
+
+        fs::write(&file, "first").unwrap();
+        target.archive(&file, "pi1-EN-1").unwrap();
+        fs::write(&file, "second").unwrap();
+        target.archive(&file, "pi1-EN-2").unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("backup/pi1-EN-1.csv")).unwrap(),
+            "first"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("backup/pi1-EN-2.csv")).unwrap(),
+            "second"
+        );
+
+        // A retry of a finished copy succeeds, a different file is refused
+        target.archive(&file, "pi1-EN-2").unwrap();
+        assert!(target.archive(&file, "pi1-EN-1").is_err());
+        assert_eq!(
+            fs::read_to_string(dir.join("backup/pi1-EN-1.csv")).unwrap(),
+            "first"
+        );
+    }
+}
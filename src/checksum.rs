@@ -0,0 +1,17 @@
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Hex encoded SHA-256 of everything read from `reader`.
+pub fn sha256_reader(mut reader: impl Read) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hex encoded SHA-256 of a file.
+pub fn sha256(path: &Path) -> Result<String> {
+    sha256_reader(File::open(path)?)
+}
@@ -0,0 +1,99 @@
+//! Command line options. Apart from `--supervise`, each one overrides a setting
+//! for this run without saving it. `merge` runs the merge tool instead of the UI.
+
+use crate::error::{Error, Result};
+use crate::merge::{Format, Input};
+use crate::supervisor;
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub supervise: bool,
+    pub listen_address: Option<String>,
+    pub listen_port: Option<u16>,
+    pub send_port: Option<u16>,
+    pub data_dir: Option<PathBuf>,
+    pub merge: Option<MergeArgs>,
+}
+
+/// `merge --input <kiosk>=<path>... --output <file> [--format csv|json]`
+#[derive(Debug)]
+pub struct MergeArgs {
+    pub inputs: Vec<Input>,
+    pub output: PathBuf,
+    pub format: Format,
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String> {
+    value.ok_or_else(|| Error::InvalidArgument(format!("Missing value for {}", flag)))
+}
+
+fn port(flag: &str, port: Option<String>) -> Result<u16> {
+    let port = value(flag, port)?;
+    port.parse()
+        .map_err(|_| Error::InvalidArgument(format!("Invalid port {} for {}", port, flag)))
+}
+
+impl MergeArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut inputs = Vec::new();
+        let mut output = None;
+        let mut format = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--input" => {
+                    let input = value(&arg, args.next())?;
+                    let (kiosk, path) = input.split_once('=').ok_or_else(|| {
+                        Error::InvalidArgument(format!(
+                            "Invalid input {}, use <kiosk>=<path>",
+                            input
+                        ))
+                    })?;
+                    inputs.push(Input {
+                        kiosk: kiosk.to_string(),
+                        path: PathBuf::from(path),
+                    });
+                }
+                "--output" => output = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--format" => format = Some(Format::parse(&value(&arg, args.next())?)?),
+                _ => return Err(Error::InvalidArgument(format!("Unknown argument {}", arg))),
+            }
+        }
+
+        if inputs.is_empty() {
+            return Err(Error::InvalidArgument(
+                "merge needs at least one --input".to_string(),
+            ));
+        }
+        let output =
+            output.ok_or_else(|| Error::InvalidArgument("merge needs --output".to_string()))?;
+        let format = format.unwrap_or_else(|| Format::from_path(&output));
+        Ok(Self {
+            inputs,
+            output,
+            format,
+        })
+    }
+}
+
+impl Args {
+    /// Parses the arguments after the program name.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "merge" => {
+                    parsed.merge = Some(MergeArgs::parse(args)?);
+                    break;
+                }
+                supervisor::FLAG => parsed.supervise = true,
+                "--listen-address" => parsed.listen_address = Some(value(&arg, args.next())?),
+                "--listen-port" => parsed.listen_port = Some(port(&arg, args.next())?),
+                "--send-port" => parsed.send_port = Some(port(&arg, args.next())?),
+                "--data-dir" => parsed.data_dir = Some(PathBuf::from(value(&arg, args.next())?)),
+                _ => return Err(Error::InvalidArgument(format!("Unknown argument {}", arg))),
+            }
+        }
+        Ok(parsed)
+    }
+}
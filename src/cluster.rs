@@ -0,0 +1,970 @@
+//! Cluster mode: kiosks with the same `cluster_name` find each other over mDNS
+//! or `cluster_peers` and keep one shared set of CSV files.
+//!
+//! The kiosk with the lowest node ID is the leader. It numbers the submitted
+//! rows, decides when tmp.csv is rotated and sends `/new_row`, and the other
+//! kiosks write its rows in the same order. Rows submitted on the others are
+//! queued until the leader has them. Kiosks that are behind ask a peer for
+//! the rows they are missing, and a new leader only takes rows once it has
+//! caught up.
+//!
+//! Each leader numbers its rows with a new term, higher than any it has seen.
+//! If two kiosks have different rows under the same number, i.e. after both
+//! led while they couldn't reach each other, they stop taking rows and report
+//! it in `/health`, as the files have to be fixed by hand. Every message is
+//! signed with `cluster_secret`, see [`osc::auth`].
+
+use crate::error::{Error, Result};
+use crate::mdns;
+use crate::osc;
+use crate::paths::{public_dir, state_dir};
+use crate::store::{load_settings, Store};
+use crate::{announce_row, append_row, count_csv_rows, finish_tmp_csv, sentences_per_csv, Row};
+use mdns_sd::{ServiceEvent, ServiceInfo};
+use rosc::{OscMessage, OscPacket, OscType};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::mem;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_PORT: u16 = 7002;
+const SERVICE_TYPE: &str = "_last-snow._udp.local.";
+const LOG_FILE: &str = "cluster-log.jsonl";
+const PENDING_FILE: &str = "cluster-pending.json";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Peers not heard from for this long are left out when picking the leader.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+/// Rows sent per sync request, so a kiosk far behind catches up in steps.
+const SYNC_BATCH: usize = 50;
+
+/// A row as stored by the leader, in the order of `seq`.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+struct Entry {
+    seq: u64,
+    /// The term of the leader that stored it, 0 for rows of older versions
+    #[serde(default)]
+    term: u64,
+    /// The kiosk the row was submitted on and its ID there, so a resent
+    /// submission isn't stored twice
+    origin: String,
+    id: u64,
+    /// Whether tmp.csv was rotated after this row
+    rotate: bool,
+    /// `None` for a rotation at closing time
+    row: Option<Row>,
+}
+
+impl Entry {
+    /// Whether both are the same row of the same leader.
+    fn same(&self, other: &Entry) -> bool {
+        self.seq == other.seq
+            && self.term == other.term
+            && self.origin == other.origin
+            && self.id == other.id
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+struct Submission {
+    id: u64,
+    row: Row,
+}
+
+struct Peer {
+    addr: SocketAddr,
+    seq: u64,
+    last_seen: Instant,
+}
+
+struct Cluster {
+    name: String,
+    node: String,
+    port: u16,
+    secret: String,
+    /// Settings at startup, for the allowed sources
+    settings: Store,
+    base_dir: PathBuf,
+    state_dir: PathBuf,
+    entries: Vec<Entry>,
+    committed: HashSet<(String, u64)>,
+    pending: Vec<Submission>,
+    next_id: u64,
+    leader: String,
+    /// The highest term seen
+    term: u64,
+    /// The term of the rows this kiosk stores while it leads
+    leader_term: Option<u64>,
+    /// Why rows are no longer taken, see [`Cluster::diverge`]
+    diverged: Option<String>,
+    peers: BTreeMap<String, Peer>,
+    /// Addresses found over mDNS, by service name
+    discovered: BTreeMap<String, Vec<SocketAddr>>,
+    static_peers: Vec<String>,
+    /// Cleared to stop the listener and the poll thread
+    running: bool,
+}
+
+type Shared = Arc<Mutex<Cluster>>;
+
+static CLUSTER: OnceLock<Shared> = OnceLock::new();
+
+fn lock(cluster: &Shared) -> MutexGuard<'_, Cluster> {
+    cluster.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn load_entries(state_dir: &Path) -> Result<Vec<Entry>> {
+    let path = state_dir.join(LOG_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for line in fs::read_to_string(&path)?.lines() {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => log::error!("Error in {}: {}", path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+fn load_pending(state_dir: &Path) -> Result<Vec<Submission>> {
+    let path = state_dir.join(PENDING_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Microseconds since the epoch, unique enough to tell submissions apart
+/// across restarts.
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+impl Cluster {
+    /// Reads the cluster settings and the rows kept in `state_dir`. `None`
+    /// when `cluster_name` isn't set.
+    fn new(settings: Store, base_dir: PathBuf, state_dir: PathBuf) -> Result<Option<Self>> {
+        let name = settings
+            .get("cluster_name")
+            .and_then(|val| val.as_str())
+            .unwrap_or_default()
+            .to_string();
+        if name.is_empty() {
+            return Ok(None);
+        }
+        let secret = settings
+            .get("cluster_secret")
+            .and_then(|val| val.as_str())
+            .unwrap_or_default()
+            .to_string();
+        if secret.is_empty() {
+            return Err(Error::Cluster(
+                "Set cluster_secret to the same value on every kiosk".to_string(),
+            ));
+        }
+        let port = settings
+            .get("cluster_port")
+            .and_then(|val| val.as_u64())
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(DEFAULT_PORT);
+        // Unique per machine and port, so instances on one machine can be told apart
+        let node = settings
+            .get("cluster_node_id")
+            .and_then(|val| val.as_str())
+            .filter(|node| !node.is_empty())
+            .map(String::from)
+            .unwrap_or_else(|| format!("{}-{}", mdns::hostname(), port));
+        let static_peers = settings
+            .get("cluster_peers")
+            .and_then(|val| val.as_array())
+            .map(|peers| {
+                peers
+                    .iter()
+                    .filter_map(|peer| peer.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let entries = load_entries(&state_dir).unwrap_or_else(|e| {
+            log::error!("Error loading cluster rows: {}", e);
+            Vec::new()
+        });
+        let pending = load_pending(&state_dir).unwrap_or_else(|e| {
+            log::error!("Error loading queued cluster rows: {}", e);
+            Vec::new()
+        });
+        let next_id = pending
+            .iter()
+            .map(|submission| submission.id + 1)
+            .chain([now_micros()])
+            .max()
+            .unwrap_or_default();
+        log::info!(
+            "Joining cluster {} as {} with {} rows, {} queued",
+            name,
+            node,
+            entries.len(),
+            pending.len()
+        );
+
+        Ok(Some(Self {
+            name,
+            node,
+            port,
+            secret,
+            settings,
+            base_dir,
+            state_dir,
+            committed: entries
+                .iter()
+                .map(|entry| (entry.origin.clone(), entry.id))
+                .collect(),
+            term: entries.last().map_or(0, |entry| entry.term),
+            leader_term: None,
+            entries,
+            pending,
+            next_id,
+            leader: String::new(),
+            diverged: None,
+            peers: BTreeMap::new(),
+            discovered: BTreeMap::new(),
+            static_peers,
+            running: true,
+        }))
+    }
+
+    fn seq(&self) -> u64 {
+        self.entries.last().map_or(0, |entry| entry.seq)
+    }
+
+    fn entry(&self, seq: u64) -> Option<&Entry> {
+        self.entries
+            .binary_search_by_key(&seq, |entry| entry.seq)
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    fn live_peers(&self) -> impl Iterator<Item = (&String, &Peer)> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| peer.last_seen.elapsed() < PEER_TIMEOUT)
+    }
+
+    fn current_leader(&self) -> String {
+        self.live_peers()
+            .map(|(node, _)| node)
+            .chain([&self.node])
+            .min()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The leader takes new rows once it has every row its peers have.
+    fn can_commit(&self) -> bool {
+        self.diverged.is_none()
+            && self.current_leader() == self.node
+            && self.live_peers().all(|(_, peer)| peer.seq <= self.seq())
+    }
+
+    /// The term for rows stored as the leader, a new one after another kiosk
+    /// led or a higher term was seen.
+    fn leader_term(&mut self) -> u64 {
+        if let Some(term) = self.leader_term.filter(|term| *term >= self.term) {
+            return term;
+        }
+        self.term += 1;
+        log::info!("Leading the cluster in term {}", self.term);
+        self.leader_term = Some(self.term);
+        self.term
+    }
+
+    /// Stops taking and writing rows, as this kiosk and a peer disagree on
+    /// what the rows are.
+    fn diverge(&mut self, reason: String) {
+        if self.diverged.is_none() {
+            log::error!("Cluster stopped: {}", reason);
+            self.diverged = Some(reason);
+        }
+    }
+
+    fn send(&self, addr: SocketAddr, command: &str, mut args: Vec<OscType>) {
+        let mut message = vec![
+            OscType::String(self.name.clone()),
+            OscType::String(self.node.clone()),
+            OscType::Int(self.port as i32),
+        ];
+        message.append(&mut args);
+        let sent = osc::auth::sign_message(
+            &self.secret,
+            OscMessage {
+                addr: command.to_string(),
+                args: message,
+            },
+        )
+        .and_then(|msg| osc::send(&addr.to_string(), &OscPacket::Message(msg)));
+        sent.unwrap_or_else(|e| {
+            log::error!("Error sending {} to {}: {}", command, addr, e);
+        });
+    }
+
+    fn send_entry(&self, addr: SocketAddr, entry: &Entry) {
+        match serde_json::to_string(entry) {
+            Ok(json) => self.send(addr, "/cluster/row", vec![OscType::String(json)]),
+            Err(e) => log::error!("Error encoding row {}: {}", entry.seq, e),
+        }
+    }
+
+    fn save_pending(&self) {
+        let saved = serde_json::to_vec(&self.pending)
+            .map_err(Error::from)
+            .and_then(|json| Ok(fs::write(self.state_dir.join(PENDING_FILE), json)?));
+        if let Err(e) = saved {
+            log::error!("Error saving queued cluster rows: {}", e);
+        }
+    }
+
+    fn record(&mut self, entry: Entry) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.state_dir.join(LOG_FILE))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        self.committed.insert((entry.origin.clone(), entry.id));
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Stores a row as the leader and sends it to the peers.
+    fn commit(&mut self, origin: &str, submission: &Submission) -> Result<()> {
+        if self
+            .committed
+            .contains(&(origin.to_string(), submission.id))
+        {
+            return Ok(());
+        }
+        let store = load_settings()?;
+        let rows = append_row(&self.base_dir, &submission.row)?;
+        let sentences_per_csv = sentences_per_csv(&store);
+        let entry = Entry {
+            seq: self.seq() + 1,
+            term: self.leader_term(),
+            origin: origin.to_string(),
+            id: submission.id,
+            rotate: rows >= sentences_per_csv,
+            row: Some(submission.row.clone()),
+        };
+        self.record(entry.clone())?;
+        log::info!(
+            "Row {} from {}, {}/{} rows in tmp.csv",
+            entry.seq,
+            origin,
+            rows,
+            sentences_per_csv
+        );
+
+        announce_row(&store, &submission.row);
+        if entry.rotate {
+            finish_tmp_csv(&self.base_dir);
+        }
+        self.broadcast(&entry);
+        Ok(())
+    }
+
+    fn broadcast(&self, entry: &Entry) {
+        for (_, peer) in self.live_peers() {
+            self.send_entry(peer.addr, entry);
+        }
+    }
+
+    /// Writes a row from the leader if it is the next one, and checks rows
+    /// this kiosk already has against it.
+    fn apply(&mut self, entry: Entry) -> Result<()> {
+        if self.diverged.is_some() {
+            return Ok(());
+        }
+        if let Some(ours) = self.entry(entry.seq) {
+            if !ours.same(&entry) {
+                let reason = format!(
+                    "row {} is {} {} in term {} here, {} {} in term {} on a peer",
+                    entry.seq, ours.origin, ours.id, ours.term, entry.origin, entry.id, entry.term
+                );
+                self.diverge(reason);
+            }
+            return Ok(());
+        }
+        if entry.seq != self.seq() + 1 {
+            return Ok(());
+        }
+        let last_term = self.entries.last().map_or(0, |last| last.term);
+        if entry.term < last_term {
+            log::warn!(
+                "Ignoring row {} of term {} from a former leader, at term {}",
+                entry.seq,
+                entry.term,
+                last_term
+            );
+            return Ok(());
+        }
+
+        if let Some(row) = &entry.row {
+            append_row(&self.base_dir, row)?;
+        }
+        let rotate = entry.rotate;
+        log::info!("Row {} from {}", entry.seq, entry.origin);
+        self.term = self.term.max(entry.term);
+        self.record(entry)?;
+        if rotate {
+            finish_tmp_csv(&self.base_dir);
+        }
+        Ok(())
+    }
+
+    /// Stores the queued rows on the leader, or sends them to it.
+    fn flush_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if self.can_commit() {
+            let node = self.node.clone();
+            for submission in mem::take(&mut self.pending) {
+                if let Err(e) = self.commit(&node, &submission) {
+                    log::error!("Error storing row: {}", e);
+                    self.pending.push(submission);
+                }
+            }
+            self.save_pending();
+            return;
+        }
+        let Some(leader) = self.peers.get(&self.current_leader()) else {
+            return;
+        };
+        for submission in &self.pending {
+            match serde_json::to_string(submission) {
+                Ok(json) => self.send(leader.addr, "/cluster/submit", vec![OscType::String(json)]),
+                Err(e) => log::error!("Error encoding row: {}", e),
+            }
+        }
+    }
+
+    /// Says hello to every known address, so peers know this kiosk and how
+    /// far it is.
+    fn poll(&mut self) {
+        let leader = self.current_leader();
+        if leader != self.leader {
+            log::info!("Cluster leader is now {}", leader);
+            if leader != self.node {
+                self.leader_term = None;
+            }
+            self.leader = leader;
+        }
+
+        let mut addresses: HashSet<SocketAddr> =
+            self.discovered.values().flatten().copied().collect();
+        addresses.extend(self.live_peers().map(|(_, peer)| peer.addr));
+        for peer in &self.static_peers {
+            match peer.to_socket_addrs() {
+                Ok(resolved) => addresses.extend(resolved),
+                Err(e) => log::debug!("Error resolving cluster peer {}: {}", peer, e),
+            }
+        }
+        let last_term = self.entries.last().map_or(0, |entry| entry.term);
+        for addr in addresses {
+            self.send(
+                addr,
+                "/cluster/hello",
+                vec![
+                    OscType::Long(self.seq() as i64),
+                    OscType::Long(last_term as i64),
+                    OscType::Long(self.term as i64),
+                ],
+            );
+        }
+
+        self.flush_pending();
+    }
+
+    /// Checks the signature of a message from a peer and handles it.
+    fn receive(&mut self, msg: OscMessage, from: SocketAddr) -> Result<()> {
+        let msg = osc::auth::authorize_peer(&self.settings, &self.secret, msg, from.ip())?;
+        self.handle(&msg, from)
+    }
+
+    fn handle(&mut self, msg: &OscMessage, from: SocketAddr) -> Result<()> {
+        let args = msg.args.as_slice();
+        let name = osc::args::string(args, 0)?;
+        let node = osc::args::string(args, 1)?;
+        let port: u16 = osc::args::int(args, 2)?;
+        // Also skips our own messages, which come back over mDNS addresses
+        if name != self.name || node == self.node {
+            return Ok(());
+        }
+        let addr = SocketAddr::new(from.ip(), port);
+
+        match msg.addr.as_str() {
+            "/cluster/hello" => {
+                osc::args::count(args, 6)?;
+                let seq: u64 = osc::args::int(args, 3)?;
+                let last_term: u64 = osc::args::int(args, 4)?;
+                let term: u64 = osc::args::int(args, 5)?;
+                if !self.peers.contains_key(&node) {
+                    log::info!("Cluster peer {} at {}", node, addr);
+                }
+                self.peers.insert(
+                    node.clone(),
+                    Peer {
+                        addr,
+                        seq,
+                        last_seen: Instant::now(),
+                    },
+                );
+                // A new leader has to outnumber every term it may have missed
+                self.term = self.term.max(term);
+                let ours = self.entry(seq).map(|entry| entry.term);
+                if ours.is_some_and(|ours| ours != last_term) {
+                    self.diverge(format!(
+                        "row {} is from term {} here, {} on {}",
+                        seq,
+                        ours.unwrap_or_default(),
+                        last_term,
+                        node
+                    ));
+                }
+                if seq > self.seq() {
+                    self.send(
+                        addr,
+                        "/cluster/sync",
+                        vec![OscType::Long(self.seq() as i64)],
+                    );
+                }
+            }
+            "/cluster/sync" => {
+                osc::args::count(args, 4)?;
+                let after: u64 = osc::args::int(args, 3)?;
+                for entry in self
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.seq > after)
+                    .take(SYNC_BATCH)
+                {
+                    self.send_entry(addr, entry);
+                }
+            }
+            "/cluster/row" => {
+                osc::args::count(args, 4)?;
+                let entry: Entry = serde_json::from_str(&osc::args::string(args, 3)?)?;
+                self.apply(entry)?;
+            }
+            "/cluster/submit" => {
+                osc::args::count(args, 4)?;
+                let submission: Submission = serde_json::from_str(&osc::args::string(args, 3)?)?;
+                // Not acknowledged otherwise, so the peer sends it again
+                if !self.committed.contains(&(node.clone(), submission.id)) {
+                    if !self.can_commit() {
+                        return Ok(());
+                    }
+                    self.commit(&node, &submission)?;
+                }
+                self.send(
+                    addr,
+                    "/cluster/ack",
+                    vec![OscType::Long(submission.id as i64)],
+                );
+            }
+            "/cluster/ack" => {
+                osc::args::count(args, 4)?;
+                let id: u64 = osc::args::int(args, 3)?;
+                let queued = self.pending.len();
+                self.pending.retain(|submission| submission.id != id);
+                if self.pending.len() != queued {
+                    self.save_pending();
+                }
+            }
+            _ => return Err(Error::UnknownAddress(msg.addr.clone())),
+        }
+        Ok(())
+    }
+
+    fn submit(&mut self, row: Row) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push(Submission { id, row });
+        self.save_pending();
+        self.flush_pending();
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        if !self.can_commit() || count_csv_rows(&self.base_dir.join("tmp.csv")) == 0 {
+            return Ok(());
+        }
+        let entry = Entry {
+            seq: self.seq() + 1,
+            term: self.leader_term(),
+            origin: self.node.clone(),
+            id: self.next_id,
+            rotate: true,
+            row: None,
+        };
+        self.next_id += 1;
+        self.record(entry.clone())?;
+        log::info!("Row {} rotates tmp.csv", entry.seq);
+        finish_tmp_csv(&self.base_dir);
+        self.broadcast(&entry);
+        Ok(())
+    }
+}
+
+fn shared() -> Result<&'static Shared> {
+    CLUSTER
+        .get()
+        .ok_or_else(|| Error::Cluster("Cluster mode is off".to_string()))
+}
+
+/// Whether cluster mode is on, see [`start`].
+pub fn enabled() -> bool {
+    CLUSTER.get().is_some()
+}
+
+/// `ok`, `off` without cluster mode, or why this kiosk stopped taking rows.
+pub fn status() -> String {
+    match CLUSTER.get() {
+        None => "off".to_string(),
+        Some(cluster) => lock(cluster)
+            .diverged
+            .clone()
+            .unwrap_or_else(|| "ok".to_string()),
+    }
+}
+
+/// Stores a submitted row: right away on the leader, otherwise it is queued
+/// until the leader has it.
+pub fn submit(row: Row) -> Result<()> {
+    lock(shared()?).submit(row);
+    Ok(())
+}
+
+/// Rotates tmp.csv on every kiosk, if this one is the leader and there is
+/// anything to rotate.
+pub fn rotate() -> Result<()> {
+    lock(shared()?).rotate()
+}
+
+fn handle_packet(cluster: &Shared, packet: OscPacket, from: SocketAddr) {
+    match packet {
+        OscPacket::Message(msg) => {
+            let addr = msg.addr.clone();
+            lock(cluster).receive(msg, from).unwrap_or_else(|e| {
+                log::error!("Error handling {} from {}: {}", addr, from, e);
+            });
+        }
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_packet(cluster, packet, from);
+            }
+        }
+    }
+}
+
+fn listen(cluster: &Shared, socket: UdpSocket) -> Result<()> {
+    log::info!("Cluster listening on {}", socket.local_addr()?);
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut buf = [0u8; rosc::decoder::MTU];
+    while lock(cluster).running {
+        let (size, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if osc::is_timeout(&e) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        match rosc::decoder::decode_udp(&buf[..size]) {
+            Ok((_, packet)) => handle_packet(cluster, packet, from),
+            Err(e) => log::warn!("Error decoding cluster packet from {}: {}", from, e),
+        }
+    }
+    Ok(())
+}
+
+/// Receives on `socket` and says hello to the peers until `running` is cleared.
+fn run(mut cluster: Cluster, socket: UdpSocket) -> Result<Shared> {
+    cluster.port = socket.local_addr()?.port();
+    let cluster = Arc::new(Mutex::new(cluster));
+
+    let listening = cluster.clone();
+    thread::spawn(move || {
+        listen(&listening, socket).unwrap_or_else(|e| {
+            log::error!("Cluster listener stopped: {}", e);
+        });
+    });
+
+    let polled = cluster.clone();
+    thread::spawn(move || loop {
+        {
+            let mut cluster = lock(&polled);
+            if !cluster.running {
+                break;
+            }
+            cluster.poll();
+        }
+        thread::sleep(POLL_INTERVAL);
+    });
+    Ok(cluster)
+}
+
+/// Announces this kiosk over mDNS and adds the kiosks of the same cluster it
+/// finds to the peers.
+fn discover(cluster: Shared) -> Result<()> {
+    let Some(daemon) = mdns::daemon() else {
+        return Ok(());
+    };
+    let (name, node, port) = {
+        let cluster = lock(&cluster);
+        (cluster.name.clone(), cluster.node.clone(), cluster.port)
+    };
+    let host = format!("{}.local.", mdns::hostname());
+    let properties = [("cluster", name.as_str()), ("node", node.as_str())];
+    let service =
+        ServiceInfo::new(SERVICE_TYPE, &node, &host, (), port, &properties[..])?.enable_addr_auto();
+    daemon.register(service)?;
+
+    let events = daemon.browse(SERVICE_TYPE)?;
+    thread::spawn(move || {
+        while let Ok(event) = events.recv() {
+            let mut cluster = lock(&cluster);
+            match event {
+                ServiceEvent::ServiceResolved(info) => {
+                    if info.get_property_val_str("cluster") != Some(name.as_str())
+                        || info.get_property_val_str("node") == Some(node.as_str())
+                    {
+                        continue;
+                    }
+                    let addresses: Vec<SocketAddr> = info
+                        .get_addresses()
+                        .iter()
+                        .map(|ip| SocketAddr::new(*ip, info.get_port()))
+                        .collect();
+                    log::info!("Found {} at {:?}", info.get_fullname(), addresses);
+                    cluster
+                        .discovered
+                        .insert(info.get_fullname().to_string(), addresses);
+                }
+                ServiceEvent::ServiceRemoved(_, fullname)
+                    if cluster.discovered.remove(&fullname).is_some() =>
+                {
+                    log::info!("Lost {}", fullname);
+                }
+                _ => {}
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Joins the cluster named by `cluster_name` in settings, if there is one.
+pub fn start(store: &Store) {
+    let cluster =
+        public_dir().and_then(|base_dir| Cluster::new(store.clone(), base_dir, state_dir()?));
+    let cluster = match cluster {
+        Ok(Some(cluster)) => cluster,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Error joining the cluster: {}", e);
+            return;
+        }
+    };
+
+    let address = osc::ListenAddress {
+        host: osc::listen_address().host,
+        port: cluster.port,
+    };
+    let cluster = address
+        .bind_or_loopback(osc::ListenAddress::bind)
+        .and_then(|socket| run(cluster, socket));
+    let cluster = match cluster {
+        Ok(cluster) => cluster,
+        Err(e) => {
+            log::error!("Error starting the cluster listener: {}", e);
+            return;
+        }
+    };
+    if CLUSTER.set(cluster.clone()).is_err() {
+        log::error!("Cluster already started");
+        return;
+    }
+
+    discover(cluster).unwrap_or_else(|e| {
+        log::error!("Error announcing the cluster over mDNS: {}", e);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::Direction;
+    use crate::paths;
+    use crate::store::StoreBuilder;
+    use serde_json::json;
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("last-snow-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn row(sentence: &str) -> Row {
+        Row {
+            language: "EN".to_string(),
+            sentence: sentence.to_string(),
+            timestamp: "2026-10-18T12:00:00+00:00".to_string(),
+            direction: Direction::Ltr,
+            test: false,
+            prompt: String::new(),
+        }
+    }
+
+    /// A kiosk with its own folders under `dir`, talking to `peers`.
+    fn kiosk(dir: &Path, node: &str, port: u16, peers: &[u16]) -> Cluster {
+        // The leader reads the row limit and `/new_row` address from here
+        paths::set_data_dir(env::temp_dir().join(format!("last-snow-data-{}", process::id())));
+        let mut settings = StoreBuilder::new(".settings".into()).build();
+        let peers: Vec<String> = peers
+            .iter()
+            .map(|port| format!("127.0.0.1:{}", port))
+            .collect();
+        for (key, value) in [
+            ("cluster_name", json!("test")),
+            ("cluster_node_id", json!(node)),
+            ("cluster_secret", json!("secret")),
+            ("cluster_port", json!(port)),
+            ("cluster_peers", json!(peers)),
+        ] {
+            settings.insert(key.to_string(), value).unwrap();
+        }
+        let base_dir = dir.join(node).join("public");
+        let state_dir = dir.join(node).join("state");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&state_dir).unwrap();
+        Cluster::new(settings, base_dir, state_dir)
+            .unwrap()
+            .unwrap()
+    }
+
+    fn wait_for(what: &str, done: impl Fn() -> bool) {
+        let started = Instant::now();
+        while !done() {
+            assert!(started.elapsed() < Duration::from_secs(20), "{}", what);
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn tmp_csv(cluster: &Shared) -> String {
+        fs::read_to_string(lock(cluster).base_dir.join("tmp.csv")).unwrap_or_default()
+    }
+
+    #[test]
+    fn keeps_rows_when_leader_goes_away() {
+        let dir = temp_dir("cluster");
+        let sockets: Vec<UdpSocket> = (0..3)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = sockets
+            .iter()
+            .map(|socket| socket.local_addr().unwrap().port())
+            .collect();
+        let nodes: Vec<Shared> = ["a", "b", "c"]
+            .into_iter()
+            .zip(sockets)
+            .enumerate()
+            .map(|(i, (node, socket))| {
+                let peers: Vec<u16> = ports
+                    .iter()
+                    .copied()
+                    .filter(|port| *port != ports[i])
+                    .collect();
+                run(kiosk(&dir, node, ports[i], &peers), socket).unwrap()
+            })
+            .collect();
+        let [a, b, c] = &nodes[..] else {
+            unreachable!()
+        };
+
+        wait_for("kiosks find each other", || {
+            nodes.iter().all(|node| {
+                let node = lock(node);
+                node.live_peers().count() == 2 && node.current_leader() == "a"
+            })
+        });
+
+        lock(b).submit(row("From b"));
+        wait_for("row reaches every kiosk", || {
+            nodes.iter().all(|node| lock(node).seq() == 1)
+        });
+        wait_for("leader acknowledges the row", || lock(b).pending.is_empty());
+
+        lock(a).running = false;
+        wait_for("b takes over", || lock(b).can_commit());
+
+        lock(c).submit(row("From c"));
+        wait_for("row reaches the remaining kiosks", || {
+            lock(b).seq() == 2 && lock(c).seq() == 2
+        });
+
+        assert_eq!(tmp_csv(b), tmp_csv(c));
+        assert!(tmp_csv(b).contains("From b") && tmp_csv(b).contains("From c"));
+        assert!(tmp_csv(a).contains("From b") && !tmp_csv(a).contains("From c"));
+        let terms: Vec<u64> = lock(c).entries.iter().map(|entry| entry.term).collect();
+        assert!(terms[1] > terms[0], "{:?}", terms);
+        assert!(lock(b).diverged.is_none() && lock(c).diverged.is_none());
+
+        for node in &nodes {
+            lock(node).running = false;
+        }
+        // The threads may still be writing for up to a poll
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn stops_on_different_rows_at_same_seq() {
+        let dir = temp_dir("cluster-diverged");
+        let mut cluster = kiosk(&dir, "b", 0, &[]);
+        cluster.leader = "b".to_string();
+        cluster
+            .commit(
+                "b",
+                &Submission {
+                    id: 1,
+                    row: row("Here"),
+                },
+            )
+            .unwrap();
+        assert!(cluster.can_commit());
+
+        let theirs = Entry {
+            seq: 1,
+            term: cluster.term,
+            origin: "a".to_string(),
+            id: 7,
+            rotate: false,
+            row: Some(row("There")),
+        };
+        cluster.apply(theirs.clone()).unwrap();
+        assert!(cluster.diverged.is_some());
+        assert!(!cluster.can_commit());
+
+        // Nothing more is written until it is fixed
+        cluster.apply(Entry { seq: 2, ..theirs }).unwrap();
+        assert_eq!(cluster.seq(), 1);
+        let written = fs::read_to_string(cluster.base_dir.join("tmp.csv")).unwrap();
+        assert!(written.contains("Here") && !written.contains("There"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
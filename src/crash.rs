@@ -0,0 +1,83 @@
+//! Crash reports in the state dir, so a crash can be looked into after the fact.
+
+use crate::error::Error;
+use crate::logging;
+use crate::paths::state_dir;
+use chrono::Local;
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic;
+use std::path::PathBuf;
+
+const LOG_LINES: usize = 100;
+const MAX_REPORTS: usize = 50;
+/// The exit code of a process whose main thread panicked, after the panic
+/// hook wrote its report.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+fn crashes_dir() -> Result<PathBuf, Error> {
+    let dir = state_dir()?.join("crashes");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Writes `crash-<time>-<pid>.txt` with the given details followed by the
+/// last lines of the log, and prunes the oldest reports.
+pub fn write_report(pid: u32, details: &str) -> Result<PathBuf, Error> {
+    let dir = crashes_dir()?;
+    let path = dir.join(format!(
+        "crash-{}-{}.txt",
+        Local::now().format("%Y%m%d-%H%M%S"),
+        pid
+    ));
+
+    let report = format!(
+        "{}\n\nLast {} log lines:\n{}\n",
+        details,
+        LOG_LINES,
+        logging::tail(LOG_LINES).join("\n")
+    );
+    fs::write(&path, report)?;
+
+    let mut reports: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    // Names start with the time, so they sort oldest first
+    reports.sort();
+    for old in reports
+        .iter()
+        .take(reports.len().saturating_sub(MAX_REPORTS))
+    {
+        fs::remove_file(old).unwrap_or_else(|e| {
+            log::error!("Error removing {}: {}", old.display(), e);
+        });
+    }
+
+    Ok(path)
+}
+
+/// Writes a crash report with the panic message and backtrace before the
+/// default hook runs.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let details = format!(
+            "Panic in thread '{}': {}\n\nBacktrace:\n{}",
+            thread.name().unwrap_or("<unnamed>"),
+            info,
+            Backtrace::force_capture()
+        );
+        log::error!(
+            "Panic in thread '{}': {}",
+            thread.name().unwrap_or("<unnamed>"),
+            info
+        );
+        match write_report(std::process::id(), &details) {
+            Ok(path) => log::error!("Crash report written to {}", path.display()),
+            Err(e) => log::error!("Error writing crash report: {}", e),
+        }
+        default_hook(info);
+    }));
+}
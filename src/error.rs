@@ -0,0 +1,104 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    Osc(rosc::OscError),
+    DBus(zbus::Error),
+    WebSocket(String),
+    PublicDir,
+    StateDir,
+    Store(String),
+    InputMethod(String),
+    InvalidArgument(String),
+    UnknownAddress(String),
+    Unauthorized(String),
+    UnsafePath(String),
+    Archive(String),
+    Mdns(String),
+    Cluster(String),
+    Ui(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Csv(e) => write!(f, "CSV error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Osc(e) => write!(f, "OSC error: {}", e),
+            Error::DBus(e) => write!(f, "D-Bus error: {}", e),
+            Error::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            Error::PublicDir => write!(f, "Could not resolve public dir"),
+            Error::StateDir => write!(f, "Could not resolve state dir"),
+            Error::Store(e) => write!(f, "Store error: {}", e),
+            Error::InputMethod(e) => write!(f, "{}", e),
+            Error::InvalidArgument(e) => write!(f, "{}", e),
+            Error::UnknownAddress(addr) => write!(f, "Unknown OSC address {}", addr),
+            Error::Unauthorized(e) => write!(f, "Unauthorized: {}", e),
+            Error::UnsafePath(e) => write!(f, "Unsafe path {}", e),
+            Error::Archive(e) => write!(f, "Archive error: {}", e),
+            Error::Mdns(e) => write!(f, "mDNS error: {}", e),
+            Error::Cluster(e) => write!(f, "Cluster error: {}", e),
+            Error::Ui(e) => write!(f, "UI error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Csv(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::DBus(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Error::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<rosc::OscError> for Error {
+    fn from(e: rosc::OscError) -> Self {
+        Error::Osc(e)
+    }
+}
+
+impl From<zbus::Error> for Error {
+    fn from(e: zbus::Error) -> Self {
+        Error::DBus(e)
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::WebSocket(e.to_string())
+    }
+}
+
+impl From<mdns_sd::Error> for Error {
+    fn from(e: mdns_sd::Error) -> Self {
+        Error::Mdns(e.to_string())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::languages::Language;
+use fontdb::{Database, Family, Query, ID};
+use std::sync::OnceLock;
+
+fn database() -> &'static Database {
+    static DATABASE: OnceLock<Database> = OnceLock::new();
+    DATABASE.get_or_init(|| {
+        let mut database = Database::new();
+        database.load_system_fonts();
+        database
+    })
+}
+
+fn find(family: &str) -> Option<ID> {
+    database().query(&Query {
+        families: &[Family::Name(family)],
+        ..Query::default()
+    })
+}
+
+/// Returns the first family of a fallback chain that is installed.
+pub fn resolve(chain: &[String]) -> Option<String> {
+    chain.iter().find(|family| find(family).is_some()).cloned()
+}
+
+/// Returns the characters of `sample` the family has no glyph for, or `None`
+/// when the family is not installed.
+fn missing_glyphs(family: &str, sample: &str) -> Option<Vec<char>> {
+    let id = find(family)?;
+    database().with_face_data(id, |data, index| {
+        let face = ttf_parser::Face::parse(data, index).ok();
+        let mut missing: Vec<char> = sample
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .filter(|c| {
+                face.as_ref()
+                    .and_then(|face| face.glyph_index(*c))
+                    .is_none()
+            })
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        missing
+    })
+}
+
+/// Logs the characters of the language's sample that its fonts can't render.
+pub fn check_coverage(language: &Language) {
+    let chain = language.font_chain();
+    let Some(primary) = resolve(&chain) else {
+        log::error!("No font installed for {}, tried {:?}", language.code, chain);
+        return;
+    };
+
+    let sample = language.font_sample();
+    let mut missing = missing_glyphs(&primary, &sample).unwrap_or_default();
+    if missing.is_empty() {
+        log::info!("Font {} covers {}", primary, language.code);
+        return;
+    }
+
+    log::warn!(
+        "Font {} for {} is missing glyphs: {}",
+        primary,
+        language.code,
+        missing.iter().collect::<String>()
+    );
+
+    // See which of the missing characters the rest of the chain can render
+    for family in chain
+        .iter()
+        .skip_while(|family| **family != primary)
+        .skip(1)
+    {
+        let remaining: String = missing.iter().collect();
+        match missing_glyphs(family, &remaining) {
+            Some(still_missing) => {
+                if still_missing.len() < missing.len() {
+                    log::info!(
+                        "Fallback font {} covers {} of them",
+                        family,
+                        missing.len() - still_missing.len()
+                    );
+                }
+                missing = still_missing;
+            }
+            None => log::warn!("Fallback font {} is not installed", family),
+        }
+        if missing.is_empty() {
+            return;
+        }
+    }
+
+    log::error!(
+        "No configured font for {} covers: {}",
+        language.code,
+        missing.iter().collect::<String>()
+    );
+}
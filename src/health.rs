@@ -0,0 +1,154 @@
+//! Liveness reporting for show control: the periodic `/heartbeat` and the `/health` query.
+
+use crate::archive;
+use crate::cluster;
+use crate::count_csv_rows;
+use crate::error::Error;
+use crate::metrics::metrics;
+use crate::osc;
+use crate::paths::public_dir;
+use crate::store::{load_settings, Store, StoreBuilder};
+use rosc::OscType;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+static INPUT_METHOD: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+/// Records the input method backend and the last error talking to it, if any.
+pub fn set_input_method(backend: &str, error: Option<&Error>) {
+    if let Ok(mut input_method) = INPUT_METHOD.lock() {
+        let status = error.map_or_else(|| "ok".to_string(), Error::to_string);
+        *input_method = Some((backend.to_string(), status));
+    }
+}
+
+fn input_method() -> (String, String) {
+    INPUT_METHOD
+        .lock()
+        .ok()
+        .and_then(|input_method| input_method.clone())
+        .unwrap_or_else(|| ("none".to_string(), "not connected".to_string()))
+}
+
+fn pending_rows() -> i32 {
+    public_dir()
+        .map(|dir| count_csv_rows(&dir.join("tmp.csv")) as i32)
+        .unwrap_or_default()
+}
+
+/// Seconds since the last submission, -1 if there was none since startup.
+fn last_submission_age() -> i32 {
+    let last = metrics().last_submission();
+    if last == 0 {
+        return -1;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    now.saturating_sub(last) as i32
+}
+
+/// `/heartbeat` arguments: uptime in seconds, version, rows in tmp.csv and last submission age.
+pub fn heartbeat() -> Vec<OscType> {
+    vec![
+        OscType::Int(metrics().uptime() as i32),
+        OscType::String(VERSION.to_string()),
+        OscType::Int(pending_rows()),
+        OscType::Int(last_submission_age()),
+    ]
+}
+
+/// `/health` arguments: overall status (`ok` or `degraded`), free disk space in MB,
+/// input method backend and its status, store status, archive status and cluster status.
+pub fn report() -> Vec<OscType> {
+    let disk_free = public_dir().and_then(|dir| fs2::available_space(dir).map_err(Error::from));
+    let (disk_free_mb, disk_ok) = match disk_free {
+        Ok(bytes) => ((bytes / 1024 / 1024) as i32, true),
+        Err(e) => {
+            log::error!("Error getting free disk space: {}", e);
+            (-1, false)
+        }
+    };
+
+    let (backend, input_method_status) = input_method();
+
+    let store_status = match StoreBuilder::new(".settings".into()).build().load() {
+        Ok(()) => "ok".to_string(),
+        Err(e) => Error::Store(e).to_string(),
+    };
+
+    let archive_status = archive::status();
+    let cluster_status = cluster::status();
+
+    let healthy = disk_ok
+        && input_method_status == "ok"
+        && store_status == "ok"
+        && archive_status == "ok"
+        && (cluster_status == "ok" || cluster_status == "off");
+
+    vec![
+        OscType::String(if healthy { "ok" } else { "degraded" }.to_string()),
+        OscType::Int(disk_free_mb),
+        OscType::String(backend),
+        OscType::String(input_method_status),
+        OscType::String(store_status),
+        OscType::String(archive_status),
+        OscType::String(cluster_status),
+    ]
+}
+
+/// `td_osc_address` followed by `heartbeat_addresses`.
+pub fn destinations(store: &Store) -> Vec<String> {
+    let mut destinations: Vec<String> = store
+        .get("heartbeat_addresses")
+        .and_then(|val| val.as_array())
+        .map(|addrs| {
+            addrs
+                .iter()
+                .filter_map(|addr| addr.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(addr) = store.get("td_osc_address").and_then(|val| val.as_str()) {
+        destinations.insert(0, addr.to_string());
+    }
+    destinations
+}
+
+/// Sends `/heartbeat` to `td_osc_address` and `heartbeat_addresses` every
+/// `heartbeat_interval_secs`. Settings are re-read on every beat, 0 pauses it.
+pub fn start() {
+    thread::spawn(|| loop {
+        let store = match load_settings() {
+            Ok(store) => store,
+            Err(e) => {
+                log::error!("Error sending /heartbeat: {}", e);
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+
+        let interval = store
+            .get("heartbeat_interval_secs")
+            .and_then(|val| val.as_u64())
+            .unwrap_or(5);
+        if interval == 0 {
+            thread::sleep(Duration::from_secs(5));
+            continue;
+        }
+
+        let packet = osc::message("/heartbeat", heartbeat());
+        for dest in destinations(&store) {
+            osc::send(&dest, &packet).unwrap_or_else(|e| {
+                log::error!("Error sending /heartbeat to {}: {}", dest, e);
+                metrics().osc_send_failure();
+            });
+        }
+
+        thread::sleep(Duration::from_secs(interval));
+    });
+}
@@ -0,0 +1,70 @@
+//! fcitx5 client using the controller interface on the session bus.
+
+use super::InputMethodBackend;
+use crate::error::Result;
+use std::{thread, time::Duration};
+use zbus::blocking::Connection;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[zbus::proxy(
+    interface = "org.fcitx.Fcitx.Controller1",
+    default_service = "org.fcitx.Fcitx5",
+    default_path = "/controller"
+)]
+trait Controller {
+    fn current_input_method(&self) -> zbus::Result<String>;
+
+    #[zbus(name = "SetCurrentIM")]
+    fn set_current_im(&self, input_method: &str) -> zbus::Result<()>;
+}
+
+pub struct Fcitx5 {
+    proxy: ControllerProxyBlocking<'static>,
+}
+
+impl Fcitx5 {
+    pub fn connect() -> Result<Self> {
+        let connection = Connection::session()?;
+        let proxy = ControllerProxyBlocking::new(&connection)?;
+
+        // Fail early when the daemon is not running
+        proxy.current_input_method()?;
+
+        Ok(Self { proxy })
+    }
+}
+
+impl InputMethodBackend for Fcitx5 {
+    fn name(&self) -> &'static str {
+        "fcitx5"
+    }
+
+    fn current_engine(&self) -> Result<String> {
+        Ok(self.proxy.current_input_method()?)
+    }
+
+    fn set_engine(&self, engine: &str) -> Result<()> {
+        Ok(self.proxy.set_current_im(engine)?)
+    }
+
+    // The controller interface has no change signal, so the current input method is polled
+    fn watch(&self, on_change: Box<dyn Fn(String) + Send>) -> Result<()> {
+        let proxy = self.proxy.clone();
+        let mut last = self.current_engine()?;
+
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            match proxy.current_input_method() {
+                Ok(current) if current != last => {
+                    last = current.clone();
+                    on_change(current);
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Error polling fcitx5 input method: {}", e),
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Minimal IBus client talking to the daemon over its private D-Bus.
+
+use super::InputMethodBackend;
+use crate::error::{Error, Result};
+use std::{env, fs, path::PathBuf, thread};
+use zbus::blocking::{Connection, ConnectionBuilder};
+use zbus::zvariant::Value;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.IBus",
+    default_service = "org.freedesktop.IBus",
+    default_path = "/org/freedesktop/IBus"
+)]
+trait IBus {
+    fn set_global_engine(&self, engine_name: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn global_engine(&self) -> zbus::Result<zbus::zvariant::OwnedValue>;
+
+    #[zbus(signal, name = "GlobalEngineChanged")]
+    fn engine_changed(&self, engine_name: String) -> zbus::Result<()>;
+}
+
+pub struct IBus {
+    proxy: IBusProxyBlocking<'static>,
+}
+
+/// Resolves the address of the IBus bus the same way libibus does: from
+/// `IBUS_ADDRESS`, or from the address file written by the daemon.
+fn bus_address() -> Result<String> {
+    if let Ok(address) = env::var("IBUS_ADDRESS") {
+        return Ok(address);
+    }
+
+    let machine_id = fs::read_to_string("/var/lib/dbus/machine-id")
+        .or_else(|_| fs::read_to_string("/etc/machine-id"))
+        .map_err(|e| Error::InputMethod(format!("Could not read machine id: {}", e)))?;
+
+    let display = env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+    let (host, display) = display.split_once(':').unwrap_or(("", display.as_str()));
+    let host = if host.is_empty() { "unix" } else { host };
+    let display_number = display.split('.').next().unwrap_or("0");
+
+    let config_dir = env::var("IBUS_CONFIG_DIR")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::config_dir().map(|dir| dir.join("ibus")))
+        .ok_or_else(|| Error::InputMethod("Could not resolve config dir".to_string()))?;
+    let address_file =
+        config_dir
+            .join("bus")
+            .join(format!("{}-{}-{}", machine_id.trim(), host, display_number));
+
+    let contents = fs::read_to_string(&address_file).map_err(|e| {
+        Error::InputMethod(format!("Could not read {}: {}", address_file.display(), e))
+    })?;
+
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("IBUS_ADDRESS="))
+        .map(|address| address.trim().to_string())
+        .ok_or_else(|| Error::InputMethod(format!("No IBUS_ADDRESS in {}", address_file.display())))
+}
+
+/// Extracts the engine name from a serialized `IBusEngineDesc`.
+fn engine_name(value: &Value) -> Option<String> {
+    match value {
+        Value::Value(inner) => engine_name(inner),
+        // ("IBusEngineDesc", attachments, name, longname, ...)
+        Value::Structure(desc) => match desc.fields().get(2) {
+            Some(Value::Str(name)) => Some(name.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl IBus {
+    pub fn connect() -> Result<Self> {
+        let address = bus_address()?;
+        let connection: Connection =
+            ConnectionBuilder::address(address.as_str()).and_then(|builder| builder.build())?;
+        let proxy = IBusProxyBlocking::new(&connection)?;
+
+        Ok(Self { proxy })
+    }
+}
+
+impl InputMethodBackend for IBus {
+    fn name(&self) -> &'static str {
+        "ibus"
+    }
+
+    fn current_engine(&self) -> Result<String> {
+        let value = self.proxy.global_engine()?;
+        engine_name(&value)
+            .ok_or_else(|| Error::InputMethod("Unexpected engine description".to_string()))
+    }
+
+    fn set_engine(&self, engine: &str) -> Result<()> {
+        Ok(self.proxy.set_global_engine(engine)?)
+    }
+
+    fn watch(&self, on_change: Box<dyn Fn(String) + Send>) -> Result<()> {
+        let signals = self.proxy.receive_engine_changed()?;
+
+        thread::spawn(move || {
+            for signal in signals {
+                match signal.args() {
+                    Ok(args) => on_change(args.engine_name),
+                    Err(e) => log::error!("Error reading GlobalEngineChanged: {}", e),
+                }
+            }
+            log::warn!("IBus engine change stream ended");
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+mod fcitx5;
+mod ibus;
+mod noop;
+
+use crate::error::{Error, Result};
+use std::env;
+
+pub trait InputMethodBackend {
+    fn name(&self) -> &'static str;
+
+    fn current_engine(&self) -> Result<String>;
+
+    fn set_engine(&self, engine: &str) -> Result<()>;
+
+    /// Calls `on_change` with the new engine id whenever the engine is changed,
+    /// including by keyboard shortcuts or other programs.
+    fn watch(&self, on_change: Box<dyn Fn(String) + Send>) -> Result<()>;
+}
+
+fn connect_backend(name: &str) -> Result<Box<dyn InputMethodBackend>> {
+    match name {
+        "ibus" => Ok(Box::new(ibus::IBus::connect()?)),
+        "fcitx5" => Ok(Box::new(fcitx5::Fcitx5::connect()?)),
+        "none" => Ok(Box::<noop::Noop>::default()),
+        _ => Err(Error::InputMethod(format!("Unknown input method {}", name))),
+    }
+}
+
+/// Connects to the input method named in settings (`ibus`, `fcitx5` or `none`),
+/// or to the first one that responds when set to `auto`.
+pub fn connect(name: &str) -> Box<dyn InputMethodBackend> {
+    let candidates = match name {
+        "auto" => {
+            let hint = env::var("XMODIFIERS")
+                .or_else(|_| env::var("GTK_IM_MODULE"))
+                .unwrap_or_default();
+            if hint.contains("fcitx") {
+                vec!["fcitx5", "ibus"]
+            } else {
+                vec!["ibus", "fcitx5"]
+            }
+        }
+        name => vec![name],
+    };
+
+    for candidate in candidates {
+        match connect_backend(candidate) {
+            Ok(backend) => {
+                log::info!("Using {} input method", backend.name());
+                return backend;
+            }
+            Err(e) => log::error!("Error connecting to {}: {}", candidate, e),
+        }
+    }
+
+    log::warn!("No input method available, language switching only affects the UI");
+    Box::<noop::Noop>::default()
+}
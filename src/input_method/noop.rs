@@ -0,0 +1,30 @@
+//! Backend for images without an input method framework, where only the
+//! X keyboard layout is used and switching languages only changes the UI.
+
+use super::InputMethodBackend;
+use crate::error::Result;
+use std::cell::RefCell;
+
+#[derive(Default)]
+pub struct Noop {
+    engine: RefCell<String>,
+}
+
+impl InputMethodBackend for Noop {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn current_engine(&self) -> Result<String> {
+        Ok(self.engine.borrow().clone())
+    }
+
+    fn set_engine(&self, engine: &str) -> Result<()> {
+        *self.engine.borrow_mut() = engine.to_string();
+        Ok(())
+    }
+
+    fn watch(&self, _on_change: Box<dyn Fn(String) + Send>) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Composition of 2-beolsik jamo into Hangul syllable blocks.
+
+const INITIALS: &str = "ㄱㄲㄴㄷㄸㄹㅁㅂㅃㅅㅆㅇㅈㅉㅊㅋㅌㅍㅎ";
+const MEDIALS: &str = "ㅏㅐㅑㅒㅓㅔㅕㅖㅗㅘㅙㅚㅛㅜㅝㅞㅟㅠㅡㅢㅣ";
+const FINALS: &str = "ㄱㄲㄳㄴㄵㄶㄷㄹㄺㄻㄼㄽㄾㄿㅀㅁㅂㅄㅅㅆㅇㅈㅊㅋㅌㅍㅎ";
+
+const COMPOUND_VOWELS: &[(char, char, char)] = &[
+    ('ㅗ', 'ㅏ', 'ㅘ'),
+    ('ㅗ', 'ㅐ', 'ㅙ'),
+    ('ㅗ', 'ㅣ', 'ㅚ'),
+    ('ㅜ', 'ㅓ', 'ㅝ'),
+    ('ㅜ', 'ㅔ', 'ㅞ'),
+    ('ㅜ', 'ㅣ', 'ㅟ'),
+    ('ㅡ', 'ㅣ', 'ㅢ'),
+];
+
+fn index(table: &str, jamo: char) -> Option<u32> {
+    table.chars().position(|c| c == jamo).map(|i| i as u32)
+}
+
+pub fn is_jamo(c: char) -> bool {
+    ('ㄱ'..='ㅣ').contains(&c)
+}
+
+/// The syllable currently being composed.
+#[derive(Debug, Default)]
+pub struct Syllable {
+    initial: Option<char>,
+    medial: Option<char>,
+    last: Option<char>,
+}
+
+impl Syllable {
+    pub fn is_empty(&self) -> bool {
+        self.initial.is_none() && self.medial.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Syllable::default();
+    }
+
+    /// Renders the syllable, falling back to bare jamo when it is incomplete.
+    pub fn render(&self) -> String {
+        match (self.initial, self.medial) {
+            (Some(initial), Some(medial)) => {
+                let l = index(INITIALS, initial).unwrap_or(0);
+                let v = index(MEDIALS, medial).unwrap_or(0);
+                let t = self
+                    .last
+                    .and_then(|last| index(FINALS, last))
+                    .map_or(0, |t| t + 1);
+                char::from_u32(0xAC00 + (l * 21 + v) * 28 + t)
+                    .map(String::from)
+                    .unwrap_or_default()
+            }
+            (Some(initial), None) => initial.to_string(),
+            (None, Some(medial)) => medial.to_string(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Feeds a jamo and returns any syllable that got completed by it.
+    pub fn push(&mut self, jamo: char) -> String {
+        let is_vowel = index(MEDIALS, jamo).is_some();
+
+        match (self.initial, self.medial, self.last, is_vowel) {
+            (None, None, _, false) => {
+                self.initial = Some(jamo);
+                String::new()
+            }
+            (Some(_), None, _, true) => {
+                self.medial = Some(jamo);
+                String::new()
+            }
+            (Some(_), Some(_), None, false) if index(FINALS, jamo).is_some() => {
+                self.last = Some(jamo);
+                String::new()
+            }
+            (Some(_), Some(medial), None, true) => {
+                if let Some(&(_, _, compound)) = COMPOUND_VOWELS
+                    .iter()
+                    .find(|(first, second, _)| *first == medial && *second == jamo)
+                {
+                    self.medial = Some(compound);
+                    return String::new();
+                }
+                let done = self.render();
+                *self = Syllable {
+                    initial: None,
+                    medial: Some(jamo),
+                    last: None,
+                };
+                done
+            }
+            (Some(_), Some(_), Some(last), true) if index(INITIALS, last).is_some() => {
+                // The final consonant moves on to start the next syllable
+                self.last = None;
+                let done = self.render();
+                *self = Syllable {
+                    initial: Some(last),
+                    medial: Some(jamo),
+                    last: None,
+                };
+                done
+            }
+            _ => {
+                let done = self.render();
+                *self = Syllable::default();
+                if is_vowel {
+                    self.medial = Some(jamo);
+                } else {
+                    self.initial = Some(jamo);
+                }
+                done
+            }
+        }
+    }
+
+    /// Removes the most recently typed jamo.
+    pub fn pop(&mut self) {
+        if self.last.take().is_none() && self.medial.take().is_none() {
+            self.initial = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(syllable: &mut Syllable, jamo: &str) -> String {
+        jamo.chars().map(|jamo| syllable.push(jamo)).collect()
+    }
+
+    #[test]
+    fn moves_final_consonant_to_next_syllable() {
+        let mut syllable = Syllable::default();
+        assert_eq!(push_all(&mut syllable, "ㅎㅏㄴ"), "");
+        assert_eq!(syllable.render(), "한");
+        assert_eq!(push_all(&mut syllable, "ㅏ"), "하");
+        assert_eq!(syllable.render(), "나");
+        assert_eq!(push_all(&mut syllable, "ㄹㄱ"), "날");
+        assert_eq!(syllable.render(), "ㄱ");
+    }
+
+    #[test]
+    fn pops_inside_syllable() {
+        let mut syllable = Syllable::default();
+        push_all(&mut syllable, "ㄱㅗㅏㅇ");
+        assert_eq!(syllable.render(), "광");
+        syllable.pop();
+        assert_eq!(syllable.render(), "과");
+        syllable.pop();
+        assert_eq!(syllable.render(), "ㄱ");
+        syllable.pop();
+        assert!(syllable.is_empty());
+        assert_eq!(syllable.render(), "");
+    }
+}
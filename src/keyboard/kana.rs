@@ -0,0 +1,152 @@
+//! Romaji to kana conversion for the on-screen keyboard.
+
+#[rustfmt::skip]
+const ROMAJI: &[(&str, &str)] = &[
+    ("a", "あ"), ("i", "い"), ("u", "う"), ("e", "え"), ("o", "お"),
+    ("ka", "か"), ("ki", "き"), ("ku", "く"), ("ke", "け"), ("ko", "こ"),
+    ("sa", "さ"), ("si", "し"), ("shi", "し"), ("su", "す"), ("se", "せ"), ("so", "そ"),
+    ("ta", "た"), ("ti", "ち"), ("chi", "ち"), ("tu", "つ"), ("tsu", "つ"), ("te", "て"), ("to", "と"),
+    ("na", "な"), ("ni", "に"), ("nu", "ぬ"), ("ne", "ね"), ("no", "の"),
+    ("ha", "は"), ("hi", "ひ"), ("hu", "ふ"), ("fu", "ふ"), ("he", "へ"), ("ho", "ほ"),
+    ("ma", "ま"), ("mi", "み"), ("mu", "む"), ("me", "め"), ("mo", "も"),
+    ("ya", "や"), ("yu", "ゆ"), ("yo", "よ"),
+    ("ra", "ら"), ("ri", "り"), ("ru", "る"), ("re", "れ"), ("ro", "ろ"),
+    ("wa", "わ"), ("wo", "を"), ("nn", "ん"), ("n'", "ん"),
+    ("ga", "が"), ("gi", "ぎ"), ("gu", "ぐ"), ("ge", "げ"), ("go", "ご"),
+    ("za", "ざ"), ("zi", "じ"), ("ji", "じ"), ("zu", "ず"), ("ze", "ぜ"), ("zo", "ぞ"),
+    ("da", "だ"), ("di", "ぢ"), ("du", "づ"), ("de", "で"), ("do", "ど"),
+    ("ba", "ば"), ("bi", "び"), ("bu", "ぶ"), ("be", "べ"), ("bo", "ぼ"),
+    ("pa", "ぱ"), ("pi", "ぴ"), ("pu", "ぷ"), ("pe", "ぺ"), ("po", "ぽ"),
+    ("kya", "きゃ"), ("kyu", "きゅ"), ("kyo", "きょ"),
+    ("sha", "しゃ"), ("shu", "しゅ"), ("sho", "しょ"), ("sya", "しゃ"), ("syu", "しゅ"), ("syo", "しょ"),
+    ("cha", "ちゃ"), ("chu", "ちゅ"), ("cho", "ちょ"), ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"),
+    ("nya", "にゃ"), ("nyu", "にゅ"), ("nyo", "にょ"),
+    ("hya", "ひゃ"), ("hyu", "ひゅ"), ("hyo", "ひょ"),
+    ("mya", "みゃ"), ("myu", "みゅ"), ("myo", "みょ"),
+    ("rya", "りゃ"), ("ryu", "りゅ"), ("ryo", "りょ"),
+    ("gya", "ぎゃ"), ("gyu", "ぎゅ"), ("gyo", "ぎょ"),
+    ("ja", "じゃ"), ("ju", "じゅ"), ("jo", "じょ"), ("jya", "じゃ"), ("jyu", "じゅ"), ("jyo", "じょ"),
+    ("bya", "びゃ"), ("byu", "びゅ"), ("byo", "びょ"),
+    ("pya", "ぴゃ"), ("pyu", "ぴゅ"), ("pyo", "ぴょ"),
+    ("fa", "ふぁ"), ("fi", "ふぃ"), ("fe", "ふぇ"), ("fo", "ふぉ"),
+    ("-", "ー"), (",", "、"), (".", "。"), ("?", "？"), ("!", "！"),
+];
+
+const VOWELS: &str = "aiueo";
+
+/// Converts as much of `buffer` to hiragana as can be decided, returning the
+/// converted text and leaving the undecided romaji tail in `buffer`.
+pub fn convert(buffer: &mut String) -> String {
+    let mut output = String::new();
+
+    loop {
+        let chars: Vec<char> = buffer.chars().collect();
+        if chars.is_empty() {
+            break;
+        }
+
+        if let Some((romaji, kana)) = ROMAJI
+            .iter()
+            .filter(|(romaji, _)| buffer.starts_with(romaji))
+            .max_by_key(|(romaji, _)| romaji.len())
+        {
+            // "n" followed by "y" or a vowel may still become に/にゃ etc.
+            let ambiguous = ROMAJI
+                .iter()
+                .any(|(other, _)| other.len() > romaji.len() && other.starts_with(buffer.as_str()));
+            if !ambiguous {
+                output.push_str(kana);
+                buffer.drain(..romaji.len());
+                continue;
+            }
+        }
+
+        if chars.len() >= 2 {
+            let (first, second) = (chars[0], chars[1]);
+            if first == second
+                && first.is_ascii_alphabetic()
+                && !VOWELS.contains(first)
+                && first != 'n'
+            {
+                output.push('っ');
+                buffer.drain(..first.len_utf8());
+                continue;
+            }
+            if first == 'n' && !VOWELS.contains(second) && second != 'y' && second != 'n' {
+                output.push('ん');
+                buffer.drain(..1);
+                continue;
+            }
+        }
+
+        if ROMAJI
+            .iter()
+            .any(|(romaji, _)| romaji.starts_with(buffer.as_str()))
+        {
+            // Wait for more input
+            break;
+        }
+
+        let first = chars[0];
+        output.push(first);
+        buffer.drain(..first.len_utf8());
+    }
+
+    output
+}
+
+/// Flushes a pending romaji tail, e.g. a trailing "n" when the visitor commits.
+pub fn flush(buffer: &mut String) -> String {
+    let output = if buffer == "n" {
+        "ん".to_string()
+    } else {
+        buffer.clone()
+    };
+    buffer.clear();
+    output
+}
+
+pub fn to_katakana(hiragana: &str) -> String {
+    hiragana
+        .chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert_all(romaji: &str) -> String {
+        let mut buffer = romaji.to_string();
+        convert(&mut buffer) + &flush(&mut buffer)
+    }
+
+    #[test]
+    fn converts_n() {
+        assert_eq!(convert_all("nn"), "ん");
+        assert_eq!(convert_all("n'"), "ん");
+        assert_eq!(convert_all("kan'i"), "かんい");
+        assert_eq!(convert_all("kanji"), "かんじ");
+        assert_eq!(convert_all("hon"), "ほん");
+    }
+
+    #[test]
+    fn waits_for_n_before_vowel() {
+        let mut buffer = "kan".to_string();
+        assert_eq!(convert(&mut buffer), "か");
+        assert_eq!(buffer, "n");
+        buffer.push('a');
+        assert_eq!(convert(&mut buffer), "な");
+    }
+
+    #[test]
+    fn doubles_consonants() {
+        assert_eq!(convert_all("kitte"), "きって");
+        assert_eq!(convert_all("zasshi"), "ざっし");
+        assert_eq!(convert_all("kippu"), "きっぷ");
+    }
+}
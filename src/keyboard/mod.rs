@@ -0,0 +1,220 @@
+mod hangul;
+mod kana;
+mod pinyin;
+
+use crate::languages::{Composition, Language};
+
+const QWERTY: [&str; 3] = [
+    "q w e r t y u i o p",
+    "a s d f g h j k l",
+    "z x c v b n m , . ?",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Direct,
+    Hiragana,
+    Katakana,
+    Pinyin,
+    Hangul,
+}
+
+/// State of the on-screen keyboard, including any text still being composed.
+pub struct Keyboard {
+    language: Language,
+    mode: Mode,
+    shift: bool,
+    buffer: String,
+    syllable: hangul::Syllable,
+}
+
+impl Keyboard {
+    pub fn new(language: Language) -> Self {
+        Self {
+            mode: Self::default_mode(&language),
+            language,
+            shift: false,
+            buffer: String::new(),
+            syllable: hangul::Syllable::default(),
+        }
+    }
+
+    fn default_mode(language: &Language) -> Mode {
+        match language.keyboard.composition {
+            Composition::Kana => Mode::Hiragana,
+            Composition::Pinyin => Mode::Pinyin,
+            Composition::Hangul => Mode::Hangul,
+            Composition::None => Mode::Direct,
+        }
+    }
+
+    /// Switches the layout, returning whatever was still being composed.
+    pub fn set_language(&mut self, language: Language) -> String {
+        let committed = self.flush();
+        *self = Keyboard::new(language);
+        committed
+    }
+
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        let layout = &self.language.keyboard;
+        // Hangul layouts only have jamo, so fall back to latin keys in direct mode
+        let rows: Vec<&str> = if layout.rows.is_empty()
+            || (layout.composition == Composition::Hangul && self.mode == Mode::Direct)
+        {
+            QWERTY.to_vec()
+        } else {
+            layout.rows.iter().map(|row| row.as_str()).collect()
+        };
+
+        rows.iter()
+            .map(|row| {
+                row.split_whitespace()
+                    .map(|key| self.shifted(key))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn shifted(&self, key: &str) -> String {
+        if !self.shift {
+            return key.to_string();
+        }
+        match key {
+            "ㅂ" => "ㅃ".to_string(),
+            "ㅈ" => "ㅉ".to_string(),
+            "ㄷ" => "ㄸ".to_string(),
+            "ㄱ" => "ㄲ".to_string(),
+            "ㅅ" => "ㅆ".to_string(),
+            "ㅐ" => "ㅒ".to_string(),
+            "ㅔ" => "ㅖ".to_string(),
+            "," => "!".to_string(),
+            "." => ":".to_string(),
+            "ß" => "ß".to_string(),
+            _ => key.to_uppercase(),
+        }
+    }
+
+    pub fn shift(&self) -> bool {
+        self.shift
+    }
+
+    pub fn toggle_shift(&mut self) {
+        self.shift = !self.shift;
+    }
+
+    pub fn has_modes(&self) -> bool {
+        self.language.keyboard.composition != Composition::None
+    }
+
+    pub fn mode_label(&self) -> &'static str {
+        match self.mode {
+            Mode::Direct => "abc",
+            Mode::Hiragana => "あ",
+            Mode::Katakana => "ア",
+            Mode::Pinyin => "中",
+            Mode::Hangul => "한",
+        }
+    }
+
+    /// Cycles the input mode (kana/romaji, pinyin/latin or hangul/latin).
+    pub fn toggle_mode(&mut self) -> String {
+        let committed = self.flush();
+        self.mode = match self.mode {
+            Mode::Hiragana => Mode::Katakana,
+            Mode::Katakana => Mode::Direct,
+            Mode::Direct => Self::default_mode(&self.language),
+            _ => Mode::Direct,
+        };
+        committed
+    }
+
+    pub fn preedit(&self) -> String {
+        match self.mode {
+            Mode::Hangul => self.syllable.render(),
+            _ => self.buffer.clone(),
+        }
+    }
+
+    pub fn candidates(&self) -> Vec<String> {
+        match self.mode {
+            Mode::Pinyin => pinyin::candidates(&self.buffer),
+            _ => vec![],
+        }
+    }
+
+    /// Handles a key press and returns the text that is ready to be committed.
+    pub fn press(&mut self, key: &str) -> String {
+        let key = key.to_string();
+        self.shift = false;
+
+        match self.mode {
+            Mode::Direct => key,
+            Mode::Hiragana | Mode::Katakana => {
+                self.buffer.push_str(&key.to_lowercase());
+                let kana = kana::convert(&mut self.buffer);
+                self.kana(kana)
+            }
+            Mode::Pinyin if key.chars().all(|c| c.is_ascii_alphabetic()) => {
+                self.buffer.push_str(&key.to_lowercase());
+                String::new()
+            }
+            Mode::Hangul if key.chars().all(hangul::is_jamo) => {
+                key.chars().map(|jamo| self.syllable.push(jamo)).collect()
+            }
+            Mode::Pinyin | Mode::Hangul => self.flush() + &key,
+        }
+    }
+
+    pub fn select_candidate(&mut self, candidate: &str) -> String {
+        pinyin::select(&mut self.buffer, candidate)
+    }
+
+    pub fn space(&mut self) -> String {
+        if self.mode == Mode::Pinyin && !self.buffer.is_empty() {
+            return match self.candidates().first() {
+                Some(candidate) => {
+                    let candidate = candidate.clone();
+                    self.select_candidate(&candidate)
+                }
+                None => std::mem::take(&mut self.buffer),
+            };
+        }
+        self.flush() + " "
+    }
+
+    /// Removes the last composed character, returning false when there was
+    /// nothing being composed and the input text itself should be edited.
+    pub fn backspace(&mut self) -> bool {
+        match self.mode {
+            Mode::Hangul if !self.syllable.is_empty() => {
+                self.syllable.pop();
+                true
+            }
+            _ => self.buffer.pop().is_some(),
+        }
+    }
+
+    /// Commits everything that is still being composed.
+    pub fn flush(&mut self) -> String {
+        match self.mode {
+            Mode::Hangul => {
+                let committed = self.syllable.render();
+                self.syllable.clear();
+                committed
+            }
+            Mode::Hiragana | Mode::Katakana => {
+                let kana = kana::flush(&mut self.buffer);
+                self.kana(kana)
+            }
+            _ => std::mem::take(&mut self.buffer),
+        }
+    }
+
+    fn kana(&self, hiragana: String) -> String {
+        if self.mode == Mode::Katakana {
+            kana::to_katakana(&hiragana)
+        } else {
+            hiragana
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! A small built-in pinyin table feeding the on-screen keyboard's candidate bar.
+
+#[rustfmt::skip]
+const SYLLABLES: &[(&str, &str)] = &[
+    ("a", "啊阿"), ("ai", "爱哎矮"), ("an", "安按暗"), ("ba", "八把爸吧"), ("bai", "白百败"),
+    ("ban", "半办班"), ("bao", "包保报"), ("bei", "北被背杯"), ("ben", "本奔"), ("bi", "比笔必"),
+    ("bian", "变边便"), ("bie", "别"), ("bing", "冰病并"), ("bu", "不部步"), ("cai", "才菜彩"),
+    ("chang", "长常场唱"), ("chi", "吃持"), ("chu", "出初处"), ("chun", "春纯"), ("ci", "次此词"),
+    ("cong", "从聪"), ("da", "大打答"), ("dai", "带代待"), ("dan", "但单蛋"), ("dao", "到道倒"),
+    ("de", "的得地"), ("deng", "等灯"), ("di", "地第底弟"), ("dian", "点电店"), ("dong", "东冬动懂"),
+    ("dou", "都豆"), ("duo", "多朵"), ("er", "二儿耳"), ("fa", "发法"), ("fang", "方放房"),
+    ("fei", "飞非"), ("fen", "分份"), ("feng", "风封"), ("gan", "感干"), ("gao", "高告"),
+    ("ge", "个哥歌"), ("gei", "给"), ("gen", "跟根"), ("gong", "工公"), ("gu", "古故"),
+    ("guang", "光广"), ("guo", "国过果"), ("hai", "还海孩"), ("han", "寒汉"), ("hao", "好号"),
+    ("he", "和喝河"), ("hen", "很"), ("hong", "红"), ("hou", "后候"), ("hua", "话花化"),
+    ("huan", "欢换"), ("hui", "会回"), ("huo", "或火活"), ("ji", "几机记季"), ("jia", "家加"),
+    ("jian", "见间"), ("jiang", "将讲"), ("jiao", "叫教"), ("jie", "节姐"), ("jin", "今进近"),
+    ("jing", "经静"), ("jiu", "就九久"), ("jue", "觉"), ("kai", "开"), ("kan", "看"),
+    ("ke", "可课"), ("kuai", "快"), ("lai", "来"), ("lao", "老"), ("le", "了乐"),
+    ("leng", "冷"), ("li", "里力"), ("liang", "两亮"), ("lin", "林"), ("liu", "六留"),
+    ("ma", "吗妈马"), ("man", "慢满"), ("mei", "没美每"), ("men", "们门"), ("mi", "米"),
+    ("ming", "明名"), ("na", "那拿"), ("nan", "难南男"), ("ne", "呢"), ("neng", "能"),
+    ("ni", "你"), ("nian", "年念"), ("nin", "您"), ("nv", "女"), ("peng", "朋"),
+    ("qi", "起七气"), ("qian", "前钱千"), ("qing", "请情晴"), ("qiu", "秋"), ("qu", "去"),
+    ("ran", "然"), ("re", "热"), ("ren", "人认"), ("ri", "日"), ("rong", "容"),
+    ("san", "三"), ("shang", "上"), ("shao", "少"), ("shen", "什身"), ("sheng", "生"),
+    ("shi", "是时十事"), ("shou", "手"), ("shui", "水谁"), ("shuo", "说"), ("si", "四思"),
+    ("ta", "他她它"), ("tai", "太"), ("tian", "天"), ("ting", "听"), ("tong", "同"),
+    ("wan", "完晚"), ("wang", "忘"), ("wei", "为"), ("wen", "问"), ("wo", "我"),
+    ("wu", "五无"), ("xi", "喜"), ("xia", "下夏"), ("xian", "先"), ("xiang", "想"),
+    ("xiao", "小笑"), ("xie", "谢写"), ("xin", "心新"), ("xing", "行"), ("xue", "雪学"),
+    ("yang", "样"), ("yao", "要"), ("ye", "也"), ("yi", "一以"), ("yin", "因"),
+    ("you", "有又"), ("yu", "雨"), ("yuan", "远"), ("yue", "月"), ("zai", "在再"),
+    ("zao", "早"), ("zen", "怎"), ("zhe", "这"), ("zhen", "真"), ("zhi", "只知"),
+    ("zhong", "中"), ("zi", "自"), ("zou", "走"), ("zui", "最"), ("zuo", "做"),
+];
+
+/// Returns the longest leading syllable of `buffer` that has candidates.
+fn leading_syllable(buffer: &str) -> Option<(&'static str, &'static str)> {
+    SYLLABLES
+        .iter()
+        .filter(|(syllable, _)| buffer.starts_with(syllable))
+        .max_by_key(|(syllable, _)| syllable.len())
+        .copied()
+}
+
+pub fn candidates(buffer: &str) -> Vec<String> {
+    match leading_syllable(buffer) {
+        Some((_, characters)) => characters.chars().map(String::from).collect(),
+        None => vec![],
+    }
+}
+
+/// Commits `candidate` for the leading syllable and removes it from `buffer`.
+pub fn select(buffer: &mut String, candidate: &str) -> String {
+    if let Some((syllable, _)) = leading_syllable(buffer) {
+        buffer.drain(..syllable.len());
+        candidate.to_string()
+    } else {
+        std::mem::take(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_candidate_for_leading_syllable() {
+        let mut buffer = "nihao".to_string();
+        assert_eq!(candidates(&buffer), vec!["你"]);
+        assert_eq!(select(&mut buffer, "你"), "你");
+        assert_eq!(buffer, "hao");
+        assert_eq!(candidates(&buffer), vec!["好", "号"]);
+        assert_eq!(select(&mut buffer, "好"), "好");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn commits_buffer_without_candidates() {
+        let mut buffer = "q".to_string();
+        assert!(candidates(&buffer).is_empty());
+        assert_eq!(select(&mut buffer, "去"), "q");
+        assert!(buffer.is_empty());
+    }
+}
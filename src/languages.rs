@@ -0,0 +1,190 @@
+//! The language catalog, loaded from `languages.json` in the public dir so
+//! curators can add or remove languages per exhibition.
+
+use crate::error::{Error, Result};
+use crate::paths::public_dir;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fs;
+
+const DEFAULT_CATALOG: &str = include_str!("../config/languages.json");
+const CATALOG_FILE: &str = "languages.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Ltr => "ltr",
+            Direction::Rtl => "rtl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Composition {
+    #[default]
+    None,
+    Kana,
+    Pinyin,
+    Hangul,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct KeyboardLayout {
+    /// Space separated key labels, one string per row
+    #[serde(default)]
+    pub rows: Vec<String>,
+    #[serde(default)]
+    pub composition: Composition,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Language {
+    pub code: String,
+    pub name: String,
+    pub native_name: String,
+    /// Engine id per input method backend, e.g. `{"ibus": "anthy"}`
+    #[serde(default)]
+    pub input_engines: HashMap<String, String>,
+    #[serde(default)]
+    pub font: Option<String>,
+    /// Families tried in order when `font` is not installed
+    #[serde(default)]
+    pub font_fallbacks: Vec<String>,
+    /// Input font size in pixels
+    #[serde(default)]
+    pub font_size: Option<f32>,
+    /// Text the fonts are checked against at startup, defaults to the
+    /// thank-you message and the keyboard keys
+    #[serde(default)]
+    pub font_sample: Option<String>,
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub keyboard: KeyboardLayout,
+    pub thank_you: String,
+    pub submit_error: String,
+}
+
+impl Language {
+    pub fn font_chain(&self) -> Vec<String> {
+        self.font
+            .iter()
+            .chain(self.font_fallbacks.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn font_sample(&self) -> String {
+        match &self.font_sample {
+            Some(sample) => sample.clone(),
+            None => self
+                .keyboard
+                .rows
+                .iter()
+                .fold(self.thank_you.clone(), |sample, row| sample + row),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct LanguageCatalog {
+    languages: Vec<Language>,
+}
+
+impl LanguageCatalog {
+    fn parse(contents: &str) -> Result<Self> {
+        let catalog: LanguageCatalog = serde_json::from_str(contents)?;
+        if catalog.languages.is_empty() {
+            return Err(Error::InvalidArgument("No languages defined".to_string()));
+        }
+        Ok(catalog)
+    }
+
+    /// Loads the catalog, writing the bundled one on first run and falling
+    /// back to it when the file can't be used.
+    pub fn load() -> Self {
+        let bundled = Self::parse(DEFAULT_CATALOG).expect("invalid bundled language catalog");
+
+        let Some(path) = public_dir().ok().map(|dir| dir.join(CATALOG_FILE)) else {
+            log::error!("Could not resolve public dir, using bundled languages");
+            return bundled;
+        };
+
+        if !path.exists() {
+            log::info!("Writing default language catalog to {}", path.display());
+            fs::write(&path, DEFAULT_CATALOG).unwrap_or_else(|e| {
+                log::error!("Error writing {}: {}", path.display(), e);
+            });
+            return bundled;
+        }
+
+        match fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|contents| Self::parse(&contents))
+        {
+            Ok(catalog) => catalog,
+            Err(e) => {
+                log::error!(
+                    "Error loading {}: {}, using bundled languages",
+                    path.display(),
+                    e
+                );
+                bundled
+            }
+        }
+    }
+
+    /// Applies the `input_engines` setting used before the catalog, engine ids
+    /// by language code per backend, over the engines in the catalog.
+    pub fn apply_legacy_engines(&mut self, engines: &JsonValue) -> Result<()> {
+        let engines: HashMap<String, HashMap<String, String>> =
+            serde_json::from_value(engines.clone())?;
+        for (backend, languages) in engines {
+            for (code, engine) in languages {
+                match self
+                    .languages
+                    .iter_mut()
+                    .find(|language| language.code == code)
+                {
+                    Some(language) => {
+                        language.input_engines.insert(backend.clone(), engine);
+                    }
+                    None => log::warn!("Ignoring {} engine for unknown language {}", backend, code),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn languages(&self) -> &[Language] {
+        &self.languages
+    }
+
+    pub fn default_language(&self) -> &Language {
+        &self.languages[0]
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Language> {
+        self.languages.iter().find(|language| language.code == code)
+    }
+
+    /// Finds the language using `engine` on the given input method backend.
+    pub fn by_engine(&self, backend: &str, engine: &str) -> Option<&Language> {
+        let engine = engine.trim();
+        self.languages.iter().find(|language| {
+            language
+                .input_engines
+                .get(backend)
+                .is_some_and(|value| value == engine)
+        })
+    }
+}
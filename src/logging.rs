@@ -0,0 +1,350 @@
+//! Logging to stdout and to size/day rotated files in the state dir.
+
+use crate::error::Error;
+use crate::paths::{public_dir, state_dir};
+use crate::store::Store;
+use chrono::{DateTime, Local, NaiveDate};
+use log::LevelFilter;
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+const LOG_NAME: &str = "last-snow";
+/// Written to the public share by older versions.
+const LEGACY_LOG_FILE: &str = "output.log";
+
+pub struct LogConfig {
+    pub level: LevelFilter,
+    pub json: bool,
+    pub max_size: u64,
+    pub max_files: usize,
+    pub retention: Duration,
+}
+
+impl LogConfig {
+    pub fn from_store(store: &Store) -> Self {
+        let level = store
+            .get("log_level")
+            .and_then(|val| val.as_str())
+            .and_then(|level| LevelFilter::from_str(level).ok())
+            .unwrap_or(LevelFilter::Info);
+        let json = store.get("log_format").and_then(|val| val.as_str()) == Some("json");
+        let max_size_mb = store
+            .get("log_max_size_mb")
+            .and_then(|val| val.as_u64())
+            .unwrap_or(5);
+        let max_files = store
+            .get("log_max_files")
+            .and_then(|val| val.as_u64())
+            .unwrap_or(10) as usize;
+        let retention_days = store
+            .get("log_retention_days")
+            .and_then(|val| val.as_u64())
+            .unwrap_or(14);
+
+        Self {
+            level,
+            json,
+            max_size: max_size_mb * 1024 * 1024,
+            max_files,
+            retention: Duration::from_secs(retention_days * 24 * 60 * 60),
+        }
+    }
+}
+
+/// A log file that is rotated when it grows past `max_size` or the day changes.
+/// Rotation only happens between lines, so no line is split across files.
+struct RotatingFile {
+    dir: PathBuf,
+    file: File,
+    /// The start of a line that hasn't been finished yet.
+    pending: Vec<u8>,
+    size: u64,
+    opened: NaiveDate,
+    max_size: u64,
+    max_files: usize,
+    retention: Duration,
+}
+
+impl RotatingFile {
+    fn open(dir: PathBuf, config: &LogConfig) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.log", LOG_NAME));
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let metadata = file.metadata()?;
+        let opened = metadata
+            .modified()
+            .map(|modified| DateTime::<Local>::from(modified).date_naive())
+            .unwrap_or_else(|_| Local::now().date_naive());
+
+        let rotating = Self {
+            dir,
+            file,
+            pending: Vec::new(),
+            size: metadata.len(),
+            opened,
+            max_size: config.max_size,
+            max_files: config.max_files,
+            retention: config.retention,
+        };
+        rotating.prune();
+        Ok(rotating)
+    }
+
+    fn current_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.log", LOG_NAME))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let stamp = Local::now().format("%Y%m%d-%H%M%S%.3f").to_string();
+        let mut rotated = self.dir.join(format!("{}.{}.log", LOG_NAME, stamp));
+        // Several rotations can happen within a millisecond
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = self.dir.join(format!("{}.{}-{}.log", LOG_NAME, stamp, n));
+            n += 1;
+        }
+        fs::rename(self.current_path(), rotated)?;
+        self.file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.current_path())?;
+        self.size = 0;
+        self.opened = Local::now().date_naive();
+        self.prune();
+        Ok(())
+    }
+
+    /// Removes rotated files past the retention period or the file limit.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut rotated: Vec<(PathBuf, SystemTime)> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path != &self.current_path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(LOG_NAME) && name.ends_with(".log"))
+            })
+            .filter_map(|path| {
+                let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+                Some((path, modified))
+            })
+            .collect();
+
+        // Newest first
+        rotated.sort_by_key(|b| std::cmp::Reverse(b.1));
+
+        for (i, (path, modified)) in rotated.iter().enumerate() {
+            let expired = modified
+                .elapsed()
+                .map(|age| age > self.retention)
+                .unwrap_or(false);
+            if expired || i >= self.max_files {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A record comes in several writes, only whole lines go to the file
+        self.pending.extend_from_slice(buf);
+        let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') else {
+            return Ok(buf.len());
+        };
+        let lines: Vec<u8> = self.pending.drain(..=end).collect();
+        if self.size > 0
+            && (self.size + lines.len() as u64 > self.max_size
+                || self.opened != Local::now().date_naive())
+        {
+            self.rotate()?;
+        }
+        self.file.write_all(&lines)?;
+        self.size += lines.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn text_format(out: fern::FormatCallback, message: &std::fmt::Arguments, record: &log::Record) {
+    out.finish(format_args!(
+        "{}[{}][{}] {}",
+        Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
+        record.level(),
+        record.target(),
+        message
+    ))
+}
+
+fn json_format(out: fern::FormatCallback, message: &std::fmt::Arguments, record: &log::Record) {
+    out.finish(format_args!(
+        "{}",
+        json!({
+            "timestamp": Local::now().to_rfc3339(),
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": message.to_string(),
+        })
+    ))
+}
+
+/// Moves the log file of older versions off the public share, next to the
+/// rotated logs so it is pruned like them.
+fn move_legacy_log(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let Ok(legacy) = public_dir().map(|public| public.join(LEGACY_LOG_FILE)) else {
+        return Ok(None);
+    };
+    if !legacy.exists() {
+        return Ok(None);
+    }
+    let moved = dir.join(format!("{}.output.log", LOG_NAME));
+    // The state dir may be on another file system
+    if fs::rename(&legacy, &moved).is_err() {
+        fs::copy(&legacy, &moved)?;
+        fs::remove_file(&legacy)?;
+    }
+    Ok(Some(moved))
+}
+
+pub fn setup(config: &LogConfig) -> Result<(), fern::InitError> {
+    let dir = state_dir()
+        .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.to_string()))?
+        .join("logs");
+    let file = RotatingFile::open(dir.clone(), config)?;
+
+    let file_dispatch = fern::Dispatch::new()
+        .format(if config.json {
+            json_format
+        } else {
+            text_format
+        })
+        .chain(Box::new(file) as Box<dyn Write + Send>);
+
+    fern::Dispatch::new()
+        .level(LevelFilter::Trace)
+        .chain(
+            fern::Dispatch::new()
+                .format(text_format)
+                .chain(io::stdout()),
+        )
+        .chain(file_dispatch)
+        .apply()?;
+
+    // The dispatch accepts everything, the global max level does the filtering
+    // so it can be changed at runtime
+    log::set_max_level(config.level);
+
+    match move_legacy_log(&dir) {
+        Ok(Some(moved)) => log::info!(
+            "Moved {} off the share to {}",
+            LEGACY_LOG_FILE,
+            moved.display()
+        ),
+        Ok(None) => {}
+        Err(e) => log::error!("Error moving {} off the share: {}", LEGACY_LOG_FILE, e),
+    }
+    Ok(())
+}
+
+/// Logs to stdout only, for the supervisor so it doesn't write to the same
+/// file as the process it supervises.
+pub fn setup_console(config: &LogConfig) -> Result<(), fern::InitError> {
+    fern::Dispatch::new()
+        .level(LevelFilter::Trace)
+        .format(text_format)
+        .chain(io::stdout())
+        .apply()?;
+
+    log::set_max_level(config.level);
+    Ok(())
+}
+
+/// The last `count` lines of the current log file.
+pub fn tail(count: usize) -> Vec<String> {
+    let Ok(dir) = state_dir() else {
+        return Vec::new();
+    };
+    let path = dir.join("logs").join(format!("{}.log", LOG_NAME));
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    let lines: Vec<&str> = contents.lines().collect();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+pub fn set_level(level: &str) -> Result<(), Error> {
+    let level = LevelFilter::from_str(level)
+        .map_err(|_| Error::InvalidArgument(format!("Invalid log level {}", level)))?;
+    log::set_max_level(level);
+    log::info!("Log level set to {}", level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("last-snow-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rotates_between_lines() {
+        let dir = temp_dir("logging");
+        let config = LogConfig {
+            level: LevelFilter::Info,
+            json: false,
+            max_size: 100,
+            max_files: 100,
+            retention: Duration::from_secs(60 * 60),
+        };
+        let mut file = RotatingFile::open(dir.clone(), &config).unwrap();
+        // Written in pieces, the way the formatter does
+        for i in 0..20 {
+            write!(file, "[INFO] record {} ", i).unwrap();
+            write!(file, "{}", "x".repeat(i)).unwrap();
+            writeln!(file).unwrap();
+        }
+        file.flush().unwrap();
+
+        let mut records = Vec::new();
+        let mut files = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let contents = fs::read_to_string(entry.unwrap().path()).unwrap();
+            assert!(contents.ends_with('\n'), "split line in {:?}", contents);
+            assert!(contents.len() <= 100 || contents.lines().count() == 1);
+            for line in contents.lines() {
+                assert!(line.starts_with("[INFO] record "), "split line {:?}", line);
+                let (i, x) = line["[INFO] record ".len()..].split_once(' ').unwrap();
+                let i: usize = i.parse().unwrap();
+                assert_eq!(x, "x".repeat(i));
+                records.push(i);
+            }
+            files += 1;
+        }
+        assert!(files > 1);
+        records.sort();
+        assert_eq!(records, (0..20).collect::<Vec<_>>());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
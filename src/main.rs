@@ -1,116 +1,99 @@
 slint::include_modules!();
 
+mod archive;
+mod checksum;
+mod cli;
+mod cluster;
+mod crash;
+mod error;
+mod fonts;
+mod health;
+mod input_method;
+mod keyboard;
+mod languages;
+mod logging;
+mod manifest;
+mod mdns;
+mod merge;
+mod metrics;
+mod osc;
+mod paths;
+mod prompts;
+mod schedule;
 mod store;
+mod supervisor;
+mod systemd;
+mod trash;
 
-use crate::store::StoreBuilder;
-use chrono::Utc;
-use csv;
-use dirs::public_dir;
-use log::LevelFilter;
-use rosc::{OscPacket, OscType};
+use crate::error::Error;
+use crate::keyboard::Keyboard;
+use crate::languages::{Direction, Language, LanguageCatalog};
+use crate::logging::LogConfig;
+use crate::metrics::metrics;
+use crate::paths::public_dir;
+use crate::prompts::Prompts;
+use crate::schedule::{Schedule, Status};
+use crate::store::{load_settings, Store, StoreBuilder};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use rosc::{OscMessage, OscPacket, OscType};
 use serde_json::json;
-use slint::{ModelRc, VecModel, Weak};
-use std::net::UdpSocket;
-use std::process::Command;
+use slint::{ModelRc, SharedString, VecModel, Weak};
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::rc::Rc;
-use std::str::FromStr;
+use std::sync::Arc;
 use std::thread;
-use std::{env, fs, path::PathBuf};
-use strum::IntoEnumIterator;
-
-#[derive(Debug, strum_macros::EnumIter)]
-pub enum Languages {
-    EN,
-    JP,
-    CN,
-    KR,
-    ES,
-    FR,
-    IT,
-    DE,
-    RU,
-    PL,
-}
-
-impl Languages {
-    pub fn value(&self) -> &'static str {
-        match self {
-            Languages::EN => "xkb:us::eng",
-            Languages::JP => "anthy",
-            Languages::CN => "libpinyin",
-            Languages::KR => "hangul",
-            Languages::ES => "xkb:es::spa",
-            Languages::FR => "xkb:fr::fra",
-            Languages::IT => "xkb:it::ita",
-            Languages::DE => "xkb:de::deu",
-            Languages::RU => "xkb:ru::rus",
-            Languages::PL => "xkb:pl::pol",
-        }
-    }
-
-    pub fn from_value(value: &str) -> Option<Languages> {
-        match value {
-            "xkb:us::eng" => Some(Languages::EN),
-            "anthy" => Some(Languages::JP),
-            "libpinyin" => Some(Languages::CN),
-            "hangul" => Some(Languages::KR),
-            "xkb:es::spa" => Some(Languages::ES),
-            "xkb:fr::fra" => Some(Languages::FR),
-            "xkb:it::ita" => Some(Languages::IT),
-            "xkb:de::deu" => Some(Languages::DE),
-            "xkb:ru::rus" => Some(Languages::RU),
-            "xkb:pl::pol" => Some(Languages::PL),
-            _ => None,
-        }
-    }
-}
-
-impl FromStr for Languages {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "EN" => Ok(Languages::EN),
-            "JP" => Ok(Languages::JP),
-            "CN" => Ok(Languages::CN),
-            "KR" => Ok(Languages::KR),
-            "ES" => Ok(Languages::ES),
-            "FR" => Ok(Languages::FR),
-            "IT" => Ok(Languages::IT),
-            "DE" => Ok(Languages::DE),
-            "RU" => Ok(Languages::RU),
-            "PL" => Ok(Languages::PL),
-            _ => Err(()),
-        }
-    }
-}
-
-impl ToString for Languages {
-    fn to_string(&self) -> String {
-        match self {
-            Languages::EN => "EN".to_string(),
-            Languages::JP => "JP".to_string(),
-            Languages::CN => "CN".to_string(),
-            Languages::KR => "KR".to_string(),
-            Languages::ES => "ES".to_string(),
-            Languages::FR => "FR".to_string(),
-            Languages::IT => "IT".to_string(),
-            Languages::DE => "DE".to_string(),
-            Languages::RU => "RU".to_string(),
-            Languages::PL => "PL".to_string(),
-        }
-    }
-}
-
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+use std::time::{Duration, Instant};
+
+const CONFIRMATION_DURATION: Duration = Duration::from_secs(4);
+const ERROR_DURATION: Duration = Duration::from_secs(6);
+const DEFAULT_FONT_SIZE: f32 = 40.0;
+const LISTENER_MIN_BACKOFF: Duration = Duration::from_secs(1);
+const LISTENER_MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How often the listener checks whether it should rebind.
+const LISTENER_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the opening hours and the prompt are checked.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 struct Row {
     language: String,
     sentence: String,
     timestamp: String,
+    #[serde(default)]
+    direction: Direction,
+    /// Submitted in the test window, kept out of the sentences folder
+    #[serde(default)]
+    test: bool,
+    /// ID of the prompt shown when it was submitted
+    #[serde(default)]
+    prompt: String,
+}
+
+/// The CSV header written by this version, in the order of `Row`.
+const ROW_HEADER: [&str; 6] = [
+    "language",
+    "sentence",
+    "timestamp",
+    "direction",
+    "test",
+    "prompt",
+];
+
+/// Reads files written by any version, which have fewer columns.
+fn csv_reader() -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.flexible(true);
+    builder
 }
 
-fn count_csv_rows(file_path: &PathBuf) -> usize {
-    let rdr = csv::Reader::from_path(&file_path);
+fn count_csv_rows(file_path: &Path) -> usize {
+    let rdr = csv_reader().from_path(file_path);
     if let Ok(mut rdr) = rdr {
         if let Ok(records) = rdr.records().collect::<Result<Vec<_>, _>>() {
             return records.len();
@@ -119,341 +102,1155 @@ fn count_csv_rows(file_path: &PathBuf) -> usize {
     0
 }
 
-fn get_new_filename(base_dir: &PathBuf) -> PathBuf {
+/// Whether the CSV file has rows under another header than `ROW_HEADER`,
+/// i.e. it was started by an older version.
+fn has_outdated_header(file_path: &Path) -> bool {
+    match csv_reader()
+        .from_path(file_path)
+        .and_then(|mut rdr| rdr.headers().cloned())
+    {
+        Ok(headers) => !headers.is_empty() && !headers.iter().eq(ROW_HEADER),
+        Err(_) => false,
+    }
+}
+
+fn get_new_filename(base_dir: &Path) -> Result<PathBuf, Error> {
     let mut last_csv_idx = 0;
-    fs::create_dir_all(base_dir.join("sentences")).unwrap();
-
-    // List all csv files in the directory
-    fs::read_dir(base_dir.join("sentences"))
-        .unwrap()
-        .for_each(|entry| {
-            let entry = entry.unwrap();
-            last_csv_idx = std::cmp::max(
-                entry
-                    .path()
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap(),
-                last_csv_idx,
-            );
-        });
+    let sentences_dir = base_dir.join("sentences");
+    fs::create_dir_all(&sentences_dir)?;
+
+    // List all csv files in the directory, skipping anything not named by index
+    for entry in fs::read_dir(&sentences_dir)? {
+        let idx = entry?
+            .path()
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<usize>().ok());
+        if let Some(idx) = idx {
+            last_csv_idx = std::cmp::max(idx, last_csv_idx);
+        }
+    }
 
-    base_dir
-        .join("sentences")
-        .join(format!("{}.csv", last_csv_idx + 1))
+    Ok(sentences_dir.join(format!("{}.csv", last_csv_idx + 1)))
 }
 
-fn remove_file_if_exists(file_path: &PathBuf) {
-    log::info!("Removing file {}", file_path.display());
-    if file_path.exists() {
-        fs::remove_file(file_path).unwrap_or_else(|e| {
-            log::error!("Error removing file {}: {}", file_path.display(), e);
-        });
-    } else {
-        log::info!("File {} does not exist", file_path.display());
+fn remove_all_csv(base_dir: &Path) -> Result<(), Error> {
+    let mut files = Vec::new();
+    let sentences_dir = base_dir.join("sentences");
+    if sentences_dir.exists() {
+        for entry in fs::read_dir(&sentences_dir)? {
+            let path = entry?.path();
+            if path.file_name() != Some(OsStr::new(manifest::MANIFEST_FILE)) {
+                files.push(path);
+            }
+        }
     }
-}
+    files.push(base_dir.join("tmp.csv"));
 
-fn remove_all_csv(base_dir: &PathBuf) {
-    fs::read_dir(base_dir.join("sentences"))
-        .unwrap()
-        .for_each(|entry| {
-            let entry = entry.unwrap();
-            remove_file_if_exists(&entry.path());
-        });
+    trash::move_to_trash(base_dir, &files)
+}
 
-    remove_file_if_exists(&base_dir.join("tmp.csv"));
+fn trash_retention(store: &Store) -> Duration {
+    let days = store
+        .get("trash_retention_days")
+        .and_then(|val| val.as_u64())
+        .unwrap_or(30);
+    Duration::from_secs(days * 24 * 60 * 60)
 }
 
-fn write_sentence(row: &Row, file_path: &PathBuf, headers: bool) {
+fn write_sentence(row: &Row, file_path: &Path, headers: bool) -> Result<(), Error> {
     let file = fs::OpenOptions::new()
-        .write(true)
         .append(true)
         .create(true)
-        .open(file_path)
-        .unwrap();
+        .open(file_path)?;
 
     let mut wtr = csv::Writer::from_writer(file);
 
     if headers {
-        wtr.serialize(row).unwrap();
+        wtr.serialize(row)?;
     } else {
-        wtr.write_record(&[&row.language, &row.sentence, &row.timestamp])
-            .unwrap();
+        wtr.write_record([
+            row.language.as_str(),
+            row.sentence.as_str(),
+            row.timestamp.as_str(),
+            row.direction.as_str(),
+            if row.test { "true" } else { "false" },
+            row.prompt.as_str(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Appends a row to a CSV file and returns how many rows it has now.
+fn append_to(file_path: &Path, row: &Row) -> Result<usize, Error> {
+    let rows = count_csv_rows(file_path);
+    write_sentence(row, file_path, rows == 0)?;
+    Ok(rows + 1)
+}
+
+fn append_row(base_dir: &Path, row: &Row) -> Result<usize, Error> {
+    append_to(&base_dir.join("tmp.csv"), row)
+}
+
+fn sentences_per_csv(store: &Store) -> usize {
+    match store
+        .get("max_sentences_per_csv")
+        .and_then(|val| val.as_i64())
+    {
+        Some(val) => val as usize,
+        None => {
+            log::error!("Error getting max_sentences_per_csv");
+            100
+        }
+    }
+}
+
+fn announce_row(store: &Store, row: &Row) {
+    match store.get("td_osc_address").and_then(|val| val.as_str()) {
+        Some(addr) => send_new_row(addr, row).unwrap_or_else(|e| {
+            log::error!("Error sending /new_row to {}: {}", addr, e);
+            metrics().osc_send_failure();
+        }),
+        None => log::error!("Error getting td_osc_address"),
+    };
+}
+
+/// Moves tmp.csv to the sentences folder and lets the manifest and the
+/// archive know about it.
+fn finish_tmp_csv(base_dir: &Path) {
+    // The sentence is already stored in tmp.csv, so a failed rotation is
+    // retried on the next submission instead of being reported to the visitor.
+    match rotate_tmp_csv(base_dir) {
+        Ok(file) => {
+            metrics().csv_rotated();
+            manifest::update_and_announce(base_dir);
+            archive::enqueue(&file);
+        }
+        Err(e) => log::error!("Error rotating tmp.csv: {}", e),
+    }
+}
+
+/// Rotates tmp.csv when the kiosk closes, so each day ends in its own files.
+fn rotate_at_closing() {
+    if cluster::enabled() {
+        cluster::rotate().unwrap_or_else(|e| {
+            log::error!("Error rotating tmp.csv at closing: {}", e);
+        });
+        return;
+    }
+    match public_dir() {
+        Ok(base_dir) => {
+            if count_csv_rows(&base_dir.join("tmp.csv")) > 0 {
+                finish_tmp_csv(&base_dir);
+            }
+        }
+        Err(e) => log::error!("Error resolving public dir: {}", e),
+    }
+}
+
+/// Whether the first row of `file_path` is from a day before `today`, in
+/// local time.
+fn started_before(file_path: &Path, today: NaiveDate) -> bool {
+    let Ok(mut reader) = csv_reader().from_path(file_path) else {
+        return false;
+    };
+    let first = reader.deserialize::<Row>().next();
+    first
+        .and_then(|row| row.ok())
+        .and_then(|row| DateTime::parse_from_rfc3339(&row.timestamp).ok())
+        .is_some_and(|timestamp| timestamp.with_timezone(&Local).date_naive() < today)
+}
+
+/// Rotates rows left from an earlier day when the kiosk opens, i.e. when it
+/// was switched off before closing time.
+fn rotate_at_opening() {
+    match public_dir() {
+        Ok(base_dir) => {
+            if started_before(&base_dir.join("tmp.csv"), Local::now().date_naive()) {
+                log::info!("tmp.csv has rows from an earlier day");
+                rotate_at_closing();
+            }
+        }
+        Err(e) => log::error!("Error resolving public dir: {}", e),
     }
-    wtr.flush().unwrap();
 }
 
-fn submit_sentence(language: &str, text: &str) -> Result<(), String> {
-    let base_dir = public_dir().unwrap();
+/// Moves test.csv to the trash when the test window ends, so each test starts
+/// with an empty file.
+fn clear_test_csv() {
+    let cleared = public_dir().and_then(|base_dir| {
+        let test_csv = base_dir.join("test.csv");
+        if !test_csv.exists() {
+            return Ok(());
+        }
+        log::info!("Moving test.csv to the trash after the test window");
+        trash::move_to_trash(&base_dir, &[test_csv])?;
+        trash::prune(&base_dir, trash_retention(&load_settings()?));
+        Ok(())
+    });
+    cleared.unwrap_or_else(|e| log::error!("Error clearing test.csv: {}", e));
+}
+
+/// Rotates a tmp.csv started by an older version, so rows with the new
+/// columns go to a file of their own.
+fn rotate_outdated_tmp_csv(base_dir: &Path) {
+    if has_outdated_header(&base_dir.join("tmp.csv")) {
+        log::info!("tmp.csv has the columns of an older version");
+        finish_tmp_csv(base_dir);
+    }
+}
 
+fn submit_sentence(language: &Language, text: &str, test: bool, prompt: &str) -> Result<(), Error> {
     let row = Row {
-        language: language.to_string(),
+        language: language.code.clone(),
         sentence: text.to_string(),
         timestamp: Utc::now().to_rfc3339(),
+        direction: language.direction,
+        test,
+        prompt: prompt.to_string(),
     };
 
-    let tmp_file_path = base_dir.join("tmp.csv");
+    if row.test {
+        let rows = append_to(&public_dir()?.join("test.csv"), &row)?;
+        log::info!("{} rows in test.csv", rows);
+        let store = load_settings()?;
+        announce_row(&store, &row);
+        return Ok(());
+    }
 
-    let rows = count_csv_rows(&tmp_file_path);
-    write_sentence(&row, &tmp_file_path, rows == 0);
+    if cluster::enabled() {
+        return cluster::submit(row);
+    }
 
-    let mut store = StoreBuilder::new(".settings".into()).build();
-    let mut sentences_per_csv = 100;
+    let base_dir = public_dir()?;
+    let rows = append_row(&base_dir, &row)?;
 
-    store.load().unwrap_or_else(|e| {
-        log::error!("Error loading store: {}", e);
-    });
-    match store.get("max_sentences_per_csv") {
-        Some(val) => sentences_per_csv = val.as_i64().unwrap() as usize,
-        None => log::error!("Error getting max_sentences_per_csv"),
-    }
-    match store.get("td_osc_address") {
-        Some(val) => {
-            let addr = val.as_str().unwrap();
-            let socket = UdpSocket::bind("last-snow.local:7001").unwrap();
-            let msg = rosc::encoder::encode(&OscPacket::Message(rosc::OscMessage {
-                addr: "/new_row".to_string(),
-                args: vec![OscType::String(row.sentence)],
-            }))
-            .unwrap();
-
-            log::info!("Sending packet to {}: {:?}", addr, msg);
-
-            socket.send_to(&msg, addr).unwrap_or_else(|e| {
-                log::error!("Error sending to socket: {}", e);
-                0
-            });
-        }
-        None => log::error!("Error getting td_osc_address"),
-    };
+    let store = load_settings()?;
+    let sentences_per_csv = sentences_per_csv(&store);
+    announce_row(&store, &row);
 
-    log::info!("{}/{} rows in tmp.csv", rows + 1, sentences_per_csv);
+    log::info!("{}/{} rows in tmp.csv", rows, sentences_per_csv);
 
-    if rows + 1 >= sentences_per_csv {
-        let new_file_path = get_new_filename(&base_dir);
-        log::info!("Moving tmp.csv to {}", new_file_path.to_str().unwrap());
-        fs::rename(&tmp_file_path, &new_file_path).unwrap();
+    if rows >= sentences_per_csv {
+        finish_tmp_csv(&base_dir);
     }
 
     Ok(())
 }
 
-fn handle_packet(packet: OscPacket, ui_handle: Weak<AppWindow>) {
-    let base_dir = public_dir().unwrap();
+fn rotate_tmp_csv(base_dir: &Path) -> Result<PathBuf, Error> {
+    let new_file_path = get_new_filename(base_dir)?;
+    log::info!("Moving tmp.csv to {}", new_file_path.display());
+    fs::rename(base_dir.join("tmp.csv"), &new_file_path)?;
+    Ok(new_file_path)
+}
 
-    let mut store = StoreBuilder::new(".settings".into()).build();
+fn send_new_row(addr: &str, row: &Row) -> Result<(), Error> {
+    let command = if row.test { "/test_row" } else { "/new_row" };
+    log::info!("Sending {} to {}", command, addr);
+    osc::send(
+        addr,
+        &osc::message(
+            command,
+            vec![
+                OscType::String(row.sentence.clone()),
+                OscType::String(row.direction.as_str().to_string()),
+                OscType::String(row.prompt.clone()),
+            ],
+        ),
+    )
+}
 
-    store.load().unwrap_or_else(|e| {
-        log::error!("Error loading store: {}", e);
-    });
+/// Applies a single command, see the README for the list.
+fn handle_message(
+    msg: &OscMessage,
+    ui_handle: &Weak<AppWindow>,
+    peer: &osc::Peer,
+    store: &mut Store,
+) -> Result<(), Error> {
+    let args = msg.args.as_slice();
+    match msg.addr.as_str() {
+        "/td_osc_address" => {
+            osc::args::count(args, 1)?;
+            let addr = osc::args::string(args, 0)?;
+            store
+                .insert("td_osc_address".to_owned(), json!(addr))
+                .map_err(Error::Store)?;
+        }
+        "/max_characters" => {
+            osc::args::count(args, 1)?;
+            let max_characters: i32 = osc::args::int(args, 0)?;
+            store
+                .insert("max_characters".to_owned(), json!(max_characters))
+                .map_err(Error::Store)?;
+            ui_handle
+                .upgrade_in_event_loop(move |handle| handle.set_character_limit(max_characters))
+                .map_err(|e| Error::Ui(e.to_string()))?;
+        }
+        "/max_sentences_per_csv" => {
+            osc::args::count(args, 1)?;
+            let max_sentences_per_csv: u32 = osc::args::int(args, 0)?;
+            store
+                .insert(
+                    "max_sentences_per_csv".to_owned(),
+                    json!(max_sentences_per_csv),
+                )
+                .map_err(Error::Store)?;
+        }
+        "/osc_listen_address" => {
+            osc::args::count(args, 1)?;
+            let host = osc::args::string(args, 0)?;
+            store
+                .insert("osc_listen_address".to_owned(), json!(host))
+                .map_err(Error::Store)?;
+            osc::set_listen_address(osc::ListenAddress {
+                host,
+                ..osc::listen_address()
+            });
+        }
+        "/osc_listen_port" => {
+            osc::args::count(args, 1)?;
+            let port: u16 = osc::args::int(args, 0)?;
+            if port == 0 {
+                return Err(Error::InvalidArgument(
+                    "Listen port must not be 0".to_string(),
+                ));
+            }
+            store
+                .insert("osc_listen_port".to_owned(), json!(port))
+                .map_err(Error::Store)?;
+            osc::set_listen_address(osc::ListenAddress {
+                port,
+                ..osc::listen_address()
+            });
+        }
+        "/osc_send_port" => {
+            osc::args::count(args, 1)?;
+            let port: u16 = osc::args::int(args, 0)?;
+            store
+                .insert("osc_send_port".to_owned(), json!(port))
+                .map_err(Error::Store)?;
+            osc::set_send_port(port);
+        }
+        "/log_level" => {
+            osc::args::count(args, 1)?;
+            let level = osc::args::string(args, 0)?;
+            logging::set_level(&level)?;
+            store
+                .insert("log_level".to_owned(), json!(level.to_lowercase()))
+                .map_err(Error::Store)?;
+        }
+        "/health" => {
+            osc::args::count(args, 0)?;
+            peer.reply("/health", health::report())?;
+        }
+        "/remove_all_csv" => {
+            osc::args::count(args, 0)?;
+            let base_dir = public_dir()?;
+            remove_all_csv(&base_dir)?;
+            manifest::update_and_announce(&base_dir);
+            trash::prune(&base_dir, trash_retention(store));
+        }
+        "/remove_output_csv" => {
+            osc::args::count(args, 1)?;
+            let filename = osc::args::string(args, 0)?;
+            let base_dir = public_dir()?;
+            let file = paths::resolve(&base_dir.join("sentences"), &filename)?;
+            if file.file_name() == Some(OsStr::new(manifest::MANIFEST_FILE)) {
+                return Err(Error::InvalidArgument(format!(
+                    "{} is not an output CSV",
+                    filename
+                )));
+            }
+            trash::move_to_trash(&base_dir, &[file])?;
+            manifest::update_and_announce(&base_dir);
+            trash::prune(&base_dir, trash_retention(store));
+        }
+        "/remove_tmp_csv" => {
+            osc::args::count(args, 0)?;
+            let base_dir = public_dir()?;
+            trash::move_to_trash(&base_dir, &[base_dir.join("tmp.csv")])?;
+            trash::prune(&base_dir, trash_retention(store));
+        }
+        "/trash/list" => {
+            osc::args::count(args, 0)?;
+            let names = trash::list(&public_dir()?)?;
+            peer.reply(
+                "/trash/list",
+                names.into_iter().map(OscType::String).collect(),
+            )?;
+        }
+        "/trash/restore" => {
+            osc::args::count(args, 1)?;
+            let name = osc::args::string(args, 0)?;
+            let base_dir = public_dir()?;
+            trash::restore(&base_dir, &name)?;
+            manifest::update_and_announce(&base_dir);
+        }
+        "/trash/empty" => match args {
+            [] => {
+                let token = trash::confirm_token();
+                peer.reply("/trash/confirm", vec![OscType::String(token)])?;
+            }
+            _ => {
+                osc::args::count(args, 1)?;
+                let token = osc::args::string(args, 0)?;
+                trash::empty(&public_dir()?, &token)?;
+            }
+        },
+        _ => {
+            metrics().osc_invalid();
+            return Err(Error::UnknownAddress(msg.addr.clone()));
+        }
+    }
 
+    store.save().map_err(Error::Store)
+}
+
+/// Handles each message in the packet and answers it with `/ok <addr>` or
+/// `/error <addr> <reason>`. Replies such as `/ok` are only counted.
+fn handle_packet(packet: OscPacket, ui_handle: Weak<AppWindow>, peer: &osc::Peer) {
     match packet {
         OscPacket::Message(msg) => {
             log::info!("Received packet: {:?}", msg);
-
-            match (msg.addr.as_str(), msg.args.as_slice()) {
-                ("/td_osc_address", [OscType::String(addr)]) => {
-                    store
-                        .insert("td_osc_address".to_owned(), json!(addr))
-                        .unwrap_or_else(|e| {
-                            log::error!("Error inserting td_osc_address: {}", e);
-                        });
-                }
-                ("/max_characters", [OscType::Int(max_characters)]) => {
-                    let max_characters = *max_characters;
-                    store
-                        .insert("max_characters".to_owned(), json!(max_characters))
-                        .unwrap_or_else(|e| {
-                            log::error!("Error inserting max_characters: {}", e);
-                        });
-                    ui_handle
-                        .upgrade_in_event_loop(move |handle| {
-                            handle.set_character_limit(max_characters)
-                        })
-                        .unwrap_or_else(|e| {
-                            log::error!("Error setting character limit: {}", e);
-                        })
-                }
-                ("/max_sentences_per_csv", [OscType::Int(max_sentences_per_csv)]) => {
-                    store
-                        .insert(
-                            "max_sentences_per_csv".to_owned(),
-                            json!(max_sentences_per_csv),
-                        )
-                        .unwrap_or_else(|e| {
-                            log::error!("Error inserting max_sentences_per_csv: {}", e);
-                        });
-                }
-                ("/remove_all_csv", []) => {
-                    remove_all_csv(&base_dir);
-                }
-                ("/remove_output_csv", [OscType::String(filename)]) => {
-                    remove_file_if_exists(&base_dir.join("sentences").join(filename));
-                }
-                ("/remove_tmp_csv", []) => {
-                    remove_file_if_exists(&base_dir.join("tmp.csv"));
-                }
-                _ => log::warn!("Invalid OSC address: {}", msg.addr),
+            if osc::is_reply(&msg.addr) {
+                log::debug!("Not answering {} from {}", msg.addr, peer.addr);
+                metrics().osc_reply();
+                return;
             }
 
-            store.save().unwrap_or_else(|e| {
-                log::error!("Error saving store: {}", e);
+            let addr = OscType::String(msg.addr.clone());
+            let handled = load_settings().and_then(|mut store| {
+                osc::auth::authorize(&store, msg.clone(), peer.addr.ip())
+                    .and_then(|msg| handle_message(&msg, &ui_handle, peer, &mut store))
+            });
+            let reply = match handled {
+                Ok(()) => peer.reply("/ok", vec![addr]),
+                Err(e) => {
+                    log::error!("Error handling {} from {}: {}", msg.addr, peer.addr, e);
+                    peer.reply("/error", vec![addr, OscType::String(e.to_string())])
+                }
+            };
+            reply.unwrap_or_else(|e| {
+                log::error!("Error replying to {}: {}", peer.addr, e);
+                metrics().osc_send_failure();
             });
         }
         OscPacket::Bundle(bundle) => {
             for packet in bundle.content {
-                handle_packet(packet, ui_handle.clone());
+                handle_packet(packet, ui_handle.clone(), peer);
             }
         }
     }
 }
 
-fn setup_logger() -> Result<(), fern::InitError> {
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "{}[{}][{}] {}",
-                chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
-                record.level(),
-                record.target(),
-                message
-            ))
-        })
-        .level(LevelFilter::Debug)
-        .chain(std::io::stdout())
-        .chain(fern::log_file(public_dir().unwrap().join("output.log"))?)
-        .apply()?;
-    Ok(())
+/// Receives and handles OSC packets until the socket fails or the listen
+/// address is changed.
+fn listen(handler: &osc::Handler) -> Result<(), Error> {
+    let address = osc::listen_address();
+    let socket = address.bind_or_loopback(osc::ListenAddress::bind)?;
+    socket.set_read_timeout(Some(LISTENER_POLL_INTERVAL))?;
+    log::info!("Listening on {}", socket.local_addr()?);
+    let local_addr = socket.local_addr()?;
+    // Nobody else can reach the fallback address, so it isn't announced
+    if local_addr.ip().is_loopback() {
+        log::warn!("Not advertising OSC on {} over mDNS", local_addr);
+        mdns::withdraw(mdns::OSC_SERVICE);
+    } else {
+        mdns::advertise(mdns::OSC_SERVICE, local_addr.port(), &[]);
+    }
+
+    let mut buf = [0u8; rosc::decoder::MTU];
+
+    loop {
+        if osc::listen_address() != address {
+            log::info!("Listen address changed to {}", osc::listen_address());
+            return Ok(());
+        }
+
+        let (size, addr) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if osc::is_timeout(&e) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let peer = osc::Peer {
+            transport: osc::Transport::Udp(&socket),
+            addr,
+        };
+        osc::dispatch(&buf[..size], &peer, handler);
+    }
 }
 
-fn get_ibus_engine_output() -> Result<String, String> {
-    let output = Command::new("ibus")
-        .arg("engine")
-        .output()
-        .map_err(|e| e.to_string())?;
+fn update_keyboard(ui: &AppWindow, keyboard: &Keyboard) {
+    let rows: Vec<ModelRc<SharedString>> = keyboard
+        .rows()
+        .into_iter()
+        .map(|row| {
+            ModelRc::from(Rc::new(VecModel::from(
+                row.into_iter().map(SharedString::from).collect::<Vec<_>>(),
+            )))
+        })
+        .collect();
+    let candidates: Vec<SharedString> = keyboard
+        .candidates()
+        .into_iter()
+        .map(SharedString::from)
+        .collect();
 
-    if output.status.success() {
-        let output_str = std::str::from_utf8(&output.stdout).unwrap().to_string();
-        Ok(output_str)
-    } else {
-        let error_str = std::str::from_utf8(&output.stderr).unwrap().to_string();
-        Err(error_str)
+    ui.set_keyboard_rows(ModelRc::from(Rc::new(VecModel::from(rows))));
+    ui.set_keyboard_candidates(ModelRc::from(Rc::new(VecModel::from(candidates))));
+    ui.set_keyboard_preedit(keyboard.preedit().into());
+    ui.set_keyboard_mode_label(keyboard.mode_label().into());
+    ui.set_keyboard_has_modes(keyboard.has_modes());
+    ui.set_keyboard_shift(keyboard.shift());
+}
+
+fn apply_language_style(ui: &AppWindow, language: &Language) {
+    let chain = language.font_chain();
+    match fonts::resolve(&chain) {
+        Some(family) => ui.set_font_family(family.into()),
+        None => {
+            log::warn!(
+                "None of the fonts {:?} for {} are installed",
+                chain,
+                language.code
+            );
+            ui.set_font_family(SharedString::default());
+        }
     }
+    ui.set_input_font_size(language.font_size.unwrap_or(DEFAULT_FONT_SIZE));
+    ui.set_rtl(language.direction == Direction::Rtl);
 }
 
-fn set_ibus_engine(language: Languages) -> Result<(), String> {
-    let output = Command::new("ibus")
-        .arg("engine")
-        .arg(language.value())
-        .output()
-        .map_err(|e| e.to_string())?;
+/// Shows `language` in the dropdown, which only has its name.
+fn show_selected_language(ui: &AppWindow, language: &Language) {
+    ui.set_selected_language(language.code.clone().into());
+    ui.set_selected_language_name(language.native_name.clone().into());
+}
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        let error_str = std::str::from_utf8(&output.stderr).unwrap().to_string();
-        Err(error_str)
+fn commit_text(ui: &AppWindow, committed: &str) {
+    if committed.is_empty() {
+        return;
     }
+    let text = format!("{}{}", ui.get_text(), committed);
+    ui.set_text_length(text.chars().count() as i32);
+    ui.set_text(text.into());
+}
+
+/// Shows the current prompt in `language` and keeps its ID in `shown`, so a
+/// sentence is saved with the prompt the visitor saw even if it changed since.
+fn show_prompt(
+    ui: &AppWindow,
+    prompts: &Prompts,
+    catalog: &LanguageCatalog,
+    language: &str,
+    shown: &RefCell<String>,
+) {
+    let prompt = prompts.current();
+    let text = prompt
+        .map(|prompt| prompt.text(language, &catalog.default_language().code))
+        .unwrap_or_default();
+    ui.set_prompt(text.into());
+    *shown.borrow_mut() = prompt.map(|prompt| prompt.id.clone()).unwrap_or_default();
+}
+
+/// The settings, or the defaults when there is no public dir, so the screen
+/// still follows the schedule.
+fn settings_or_defaults() -> Store {
+    load_settings().unwrap_or_else(|e| {
+        log::error!("Error loading settings: {}", e);
+        StoreBuilder::new(".settings".into()).build()
+    })
+}
+
+fn show_status(ui: &AppWindow, status: Status, store: &Store) {
+    ui.set_test_mode(status == Status::Test);
+    let Status::Closed { opens } = status else {
+        ui.set_closed(false);
+        return;
+    };
+    ui.set_closed(true);
+    ui.set_closed_text(
+        store
+            .get("closed_text")
+            .and_then(|val| val.as_str())
+            .unwrap_or_default()
+            .into(),
+    );
+    let reopening_text = match opens {
+        Some(opens) => store
+            .get("reopening_text")
+            .and_then(|val| val.as_str())
+            .unwrap_or_default()
+            .replace("{opens}", &opens.format("%A %H:%M").to_string()),
+        None => String::new(),
+    };
+    ui.set_reopening_text(reopening_text.into());
 }
 
 fn main() -> Result<(), slint::PlatformError> {
+    let args = cli::Args::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    // No logger here, the merged rows may be going to stdout
+    if let Some(merge) = args.merge {
+        match merge::run(&merge.inputs, &merge.output, merge.format) {
+            Ok(rows) => {
+                eprintln!("Wrote {} rows to {}", rows, merge.output.display());
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Error merging: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(data_dir) = args.data_dir {
+        paths::set_data_dir(data_dir);
+    }
+    if args.supervise {
+        supervisor::run();
+    }
+
     let ui = AppWindow::new()?;
 
-    setup_logger().unwrap();
     let mut store = StoreBuilder::new(".settings".into()).build();
+    let store_loaded = store.load();
+
+    logging::setup(&LogConfig::from_store(&store)).unwrap_or_else(|e| {
+        eprintln!("Error setting up logger: {}", e);
+    });
 
-    store.load().unwrap_or_else(|e| {
+    store_loaded.unwrap_or_else(|e| {
         log::error!("Error loading store: {}", e);
     });
 
-    if !store.has("max_characters") {
-        store
-            .insert("max_characters".to_owned(), json!(160))
-            .unwrap_or_else(|e| {
-                log::error!("Error inserting max_characters: {}", e);
-            });
+    crash::install_panic_hook();
+    metrics().set_restarts(supervisor::restarts());
+
+    let defaults = [
+        ("max_characters", json!(160)),
+        ("max_sentences_per_csv", json!(100)),
+        ("input_method", json!("auto")),
+        ("on_screen_keyboard", json!(true)),
+        ("log_level", json!("info")),
+        ("log_format", json!("text")),
+        ("log_max_size_mb", json!(5)),
+        ("log_max_files", json!(10)),
+        ("log_retention_days", json!(14)),
+        ("metrics_port", json!(9110)),
+        ("heartbeat_interval_secs", json!(5)),
+        ("heartbeat_addresses", json!([])),
+        ("osc_listen_address", json!(osc::DEFAULT_LISTEN_HOST)),
+        ("osc_listen_port", json!(osc::DEFAULT_LISTEN_PORT)),
+        ("osc_send_port", json!(0)),
+        ("osc_tcp_port", json!(osc::DEFAULT_LISTEN_PORT)),
+        ("osc_websocket_port", json!(7001)),
+        ("osc_allowed_sources", json!([])),
+        ("osc_password", json!("")),
+        ("trash_retention_days", json!(30)),
+        ("archive_targets", json!([])),
+        ("installation_name", json!("")),
+        ("opening_hours", json!({})),
+        ("holidays", json!({})),
+        ("test_window", json!([])),
+        ("closed_text", json!("Closed")),
+        ("reopening_text", json!("Open again {opens}")),
+        ("prompts", json!([])),
+        ("prompt_rotation_minutes", json!(24 * 60)),
+        ("mdns_advertise", json!(true)),
+        ("cluster_name", json!("")),
+        ("cluster_node_id", json!("")),
+        ("cluster_secret", json!("")),
+        ("cluster_port", json!(cluster::DEFAULT_PORT)),
+        ("cluster_peers", json!([])),
+    ];
+
+    // Binding to the old default host name fails when the machine is named
+    // differently, but it may have been set on purpose, so it is kept
+    if store.get("osc_listen_address").and_then(|val| val.as_str()) == Some(osc::LEGACY_LISTEN_HOST)
+    {
+        log::warn!(
+            "osc_listen_address is {}, only reachable while the host has that name; set it to {} to listen on all interfaces",
+            osc::LEGACY_LISTEN_HOST,
+            osc::DEFAULT_LISTEN_HOST
+        );
     }
 
-    if !store.has("max_sentences_per_csv") {
-        store
-            .insert("max_sentences_per_csv".to_owned(), json!(100))
-            .unwrap_or_else(|e| {
-                log::error!("Error inserting max_sentences_per_csv: {}", e);
+    for (key, value) in defaults {
+        if !store.has(key) {
+            store.insert(key.to_owned(), value).unwrap_or_else(|e| {
+                log::error!("Error inserting {}: {}", key, e);
             });
+        }
     }
 
     store.save().unwrap_or_else(|e| {
         log::error!("Error saving store: {}", e);
     });
 
-    let max_characters = store.get("max_characters").unwrap().as_i64().unwrap() as i32;
-    ui.set_character_limit(max_characters);
+    match public_dir() {
+        Ok(base_dir) => {
+            trash::prune(&base_dir, trash_retention(&store));
+            rotate_outdated_tmp_csv(&base_dir);
+            // Files may have been changed through the share while we weren't running
+            manifest::update_and_announce(&base_dir);
+        }
+        Err(e) => log::error!("Error resolving public dir: {}", e),
+    }
 
-    let ui_handle = ui.as_weak();
-    let model = Rc::new(VecModel::from(
-        Languages::iter()
-            .map(|l| l.to_string().into())
-            .collect::<Vec<_>>(),
-    ));
-    ui.set_languages(ModelRc::from(model.clone()));
-
-    let language = get_ibus_engine_output().unwrap_or_else(|e| {
-        log::error!("Error getting ibus engine output: {}", e);
-        Languages::EN.value().to_string()
+    // Command line options win over the settings, without replacing them
+    osc::set_listen_address(osc::ListenAddress {
+        host: args
+            .listen_address
+            .or_else(|| {
+                store
+                    .get("osc_listen_address")
+                    .and_then(|val| val.as_str())
+                    .map(String::from)
+            })
+            .unwrap_or_else(|| osc::DEFAULT_LISTEN_HOST.to_string()),
+        port: args
+            .listen_port
+            .or_else(|| {
+                store
+                    .get("osc_listen_port")
+                    .and_then(|val| val.as_u64())
+                    .and_then(|port| u16::try_from(port).ok())
+            })
+            .unwrap_or(osc::DEFAULT_LISTEN_PORT),
     });
+    osc::set_send_port(
+        args.send_port
+            .or_else(|| {
+                store
+                    .get("osc_send_port")
+                    .and_then(|val| val.as_u64())
+                    .and_then(|port| u16::try_from(port).ok())
+            })
+            .unwrap_or(0),
+    );
 
-    ui.set_selected_language(
-        Languages::from_value(language.as_str())
-            .unwrap_or(Languages::EN)
-            .to_string()
-            .into(),
+    ui.set_keyboard_visible(
+        store
+            .get("on_screen_keyboard")
+            .and_then(|val| val.as_bool())
+            .unwrap_or(true),
     );
 
-    ui.on_select_language(move |language| {
-        let language = Languages::from_str(language.as_str()).unwrap();
-        set_ibus_engine(language.into()).unwrap_or_else(|e| {
-            log::error!("Error setting ibus engine: {}", e);
+    let max_characters = store
+        .get("max_characters")
+        .and_then(|val| val.as_i64())
+        .unwrap_or(160) as i32;
+    ui.set_character_limit(max_characters);
+
+    let mut catalog = LanguageCatalog::load();
+    // Kiosks set up before the catalog keep their engines until they are
+    // moved to languages.json
+    if let Some(engines) = store.get("input_engines") {
+        log::warn!("The input_engines setting is deprecated, set input_engines in languages.json");
+        catalog.apply_legacy_engines(engines).unwrap_or_else(|e| {
+            log::error!("Error in input_engines: {}", e);
+        });
+    }
+    let catalog = Arc::new(catalog);
+    for language in catalog.languages() {
+        fonts::check_coverage(language);
+    }
+    metrics().init_languages(catalog.languages().iter().map(|l| l.code.as_str()));
+
+    match store.get("metrics_port").and_then(|val| val.as_u64()) {
+        Some(0) => log::info!("Metrics endpoint disabled"),
+        Some(port) => metrics::serve(port as u16),
+        None => log::error!("Error getting metrics_port"),
+    }
+
+    health::start();
+    archive::start();
+    cluster::start(&store);
+
+    let (codes, names): (Vec<SharedString>, Vec<SharedString>) = catalog
+        .languages()
+        .iter()
+        .map(|l| (l.code.clone().into(), l.native_name.clone().into()))
+        .unzip();
+    ui.set_languages(ModelRc::from(Rc::new(VecModel::from(codes))));
+    ui.set_language_names(ModelRc::from(Rc::new(VecModel::from(names))));
+
+    let input_method = input_method::connect(
+        store
+            .get("input_method")
+            .and_then(|val| val.as_str())
+            .unwrap_or("auto"),
+    );
+
+    let current_engine = input_method.current_engine();
+    health::set_input_method(input_method.name(), current_engine.as_ref().err());
+
+    let language = current_engine
+        .map(|engine| catalog.by_engine(input_method.name(), &engine))
+        .unwrap_or_else(|e| {
+            log::error!("Error getting input method engine: {}", e);
+            None
+        })
+        .unwrap_or(catalog.default_language());
+
+    show_selected_language(&ui, language);
+    apply_language_style(&ui, language);
+
+    let ui_handle = ui.as_weak();
+    let watched_catalog = catalog.clone();
+    let backend = input_method.name();
+    input_method
+        .watch(Box::new(move |engine| {
+            log::info!("Input method engine changed to {}", engine);
+            health::set_input_method(backend, None);
+            let Some(language) = watched_catalog.by_engine(backend, &engine) else {
+                return;
+            };
+            let language = language.clone();
+            ui_handle
+                .upgrade_in_event_loop(move |ui| {
+                    if ui.get_selected_language() != language.code.as_str() {
+                        show_selected_language(&ui, &language);
+                        ui.invoke_select_language(language.code.into());
+                    }
+                })
+                .unwrap_or_else(|e| {
+                    log::error!("Error updating selected language: {}", e);
+                });
+        }))
+        .unwrap_or_else(|e| {
+            log::error!("Error watching input method engine: {}", e);
+            health::set_input_method(input_method.name(), Some(&e));
         });
+
+    let keyboard = Rc::new(RefCell::new(Keyboard::new(language.clone())));
+    update_keyboard(&ui, &keyboard.borrow());
+
+    let prompts = Rc::new(Prompts::from_store(&store).unwrap_or_else(|e| {
+        log::error!("Error in prompts: {}", e);
+        Prompts::default()
+    }));
+    let shown_prompt = Rc::new(RefCell::new(String::new()));
+    show_prompt(&ui, &prompts, &catalog, &language.code, &shown_prompt);
+
+    let ui_handle = ui.as_weak();
+    let kb = keyboard.clone();
+    let select_catalog = catalog.clone();
+    let select_prompts = prompts.clone();
+    let select_shown_prompt = shown_prompt.clone();
+    ui.on_select_language(move |code| {
+        let ui = ui_handle.unwrap();
+        let Some(language) = select_catalog.get(code.as_str()) else {
+            log::error!("Unknown language {}", code);
+            return;
+        };
+        log::info!("Selected {} ({})", language.name, language.code);
+        let committed = kb.borrow_mut().set_language(language.clone());
+        commit_text(&ui, &committed);
+        update_keyboard(&ui, &kb.borrow());
+        apply_language_style(&ui, language);
+        show_prompt(
+            &ui,
+            &select_prompts,
+            &select_catalog,
+            &language.code,
+            &select_shown_prompt,
+        );
+        let Some(engine) = language.input_engines.get(input_method.name()) else {
+            log::warn!(
+                "No {} engine configured for {}",
+                input_method.name(),
+                language.code
+            );
+            return;
+        };
+        // Changes coming from the input method itself are already applied
+        if matches!(input_method.current_engine(), Ok(current) if current == *engine) {
+            return;
+        }
+        let result = input_method.set_engine(engine);
+        if let Err(e) = &result {
+            log::error!("Error setting input method engine: {}", e);
+        }
+        health::set_input_method(input_method.name(), result.as_ref().err());
+    });
+
+    let ui_handle = ui.as_weak();
+    let kb = keyboard.clone();
+    ui.on_keyboard_key(move |key| {
+        let ui = ui_handle.unwrap();
+        let committed = kb.borrow_mut().press(key.as_str());
+        commit_text(&ui, &committed);
+        update_keyboard(&ui, &kb.borrow());
+    });
+
+    let ui_handle = ui.as_weak();
+    let kb = keyboard.clone();
+    ui.on_keyboard_candidate(move |candidate| {
+        let ui = ui_handle.unwrap();
+        let committed = kb.borrow_mut().select_candidate(candidate.as_str());
+        commit_text(&ui, &committed);
+        update_keyboard(&ui, &kb.borrow());
+    });
+
+    let ui_handle = ui.as_weak();
+    let kb = keyboard.clone();
+    ui.on_keyboard_action(move |action| {
+        let ui = ui_handle.unwrap();
+        let mut keyboard = kb.borrow_mut();
+        match action {
+            KeyboardAction::Backspace => {
+                if !keyboard.backspace() {
+                    let mut text = ui.get_text().to_string();
+                    text.pop();
+                    ui.set_text_length(text.chars().count() as i32);
+                    ui.set_text(text.into());
+                }
+            }
+            KeyboardAction::Space => commit_text(&ui, &keyboard.space()),
+            KeyboardAction::Shift => keyboard.toggle_shift(),
+            KeyboardAction::Mode => commit_text(&ui, &keyboard.toggle_mode()),
+            KeyboardAction::Hide => {
+                commit_text(&ui, &keyboard.flush());
+                ui.set_keyboard_visible(false);
+            }
+        }
+        update_keyboard(&ui, &keyboard);
     });
 
     let ui_handle = ui.as_weak();
     ui.on_text_changed(move |text| {
-        ui_handle.unwrap().set_text_length(text.len() as i32);
+        ui_handle
+            .unwrap()
+            .set_text_length(text.chars().count() as i32);
     });
 
     let ui_handle = ui.as_weak();
-    thread::spawn(move || {
-        // Bind the UDP socket to listen on port 7000
-        let socket = UdpSocket::bind("last-snow.local:7000")
-            .unwrap_or_else(|_| UdpSocket::bind("127.0.0.1:7000").unwrap());
-        log::info!("Listening on {}", socket.local_addr().unwrap());
+    let handler: osc::Handler =
+        Arc::new(move |packet, peer| handle_packet(packet, ui_handle.clone(), peer));
 
-        let mut buf = [0u8; rosc::decoder::MTU];
+    match store.get("osc_tcp_port").and_then(|val| val.as_u64()) {
+        Some(0) => log::info!("OSC over TCP disabled"),
+        Some(port) => osc::tcp::serve(port as u16, handler.clone()),
+        None => log::error!("Error getting osc_tcp_port"),
+    }
+    match store.get("osc_websocket_port").and_then(|val| val.as_u64()) {
+        Some(0) => log::info!("OSC over WebSocket disabled"),
+        Some(port) => osc::websocket::serve(port as u16, handler.clone()),
+        None => log::error!("Error getting osc_websocket_port"),
+    }
 
+    thread::spawn(move || {
+        // Restart the listener when it fails, backing off while it keeps failing
+        let mut backoff = LISTENER_MIN_BACKOFF;
         loop {
-            match socket.recv_from(&mut buf) {
-                Ok((size, addr)) => {
-                    log::info!("Received packet with size {} from: {}", size, addr);
-                    let (_, msg) = rosc::decoder::decode_udp(&buf[..size]).unwrap();
-                    handle_packet(msg, ui_handle.clone());
-                }
-                Err(e) => {
-                    log::info!("Error receiving from socket: {}", e);
-                    break;
-                }
+            let started = Instant::now();
+            match panic::catch_unwind(AssertUnwindSafe(|| listen(&handler))) {
+                // Rebind to the new address right away
+                Ok(Ok(())) => continue,
+                Ok(Err(e)) => log::error!("OSC listener stopped: {}", e),
+                Err(_) => log::error!("OSC listener panicked"),
+            }
+            if started.elapsed() > LISTENER_MAX_BACKOFF {
+                backoff = LISTENER_MIN_BACKOFF;
+            }
+            log::info!("Restarting OSC listener in {:?}", backoff);
+            thread::sleep(backoff);
+            backoff = std::cmp::min(backoff * 2, LISTENER_MAX_BACKOFF);
+        }
+    });
+    let schedule = Rc::new(Schedule::from_store(&store).unwrap_or_else(|e| {
+        log::error!("Error in opening hours, staying open: {}", e);
+        Schedule::default()
+    }));
+    let status = Rc::new(Cell::new(schedule.status()));
+    show_status(&ui, status.get(), &store);
+    if status.get() == Status::Open {
+        thread::spawn(rotate_at_opening);
+    }
+    if status.get() != Status::Test {
+        thread::spawn(clear_test_csv);
+    }
+
+    let ui_handle = ui.as_weak();
+    let checked_schedule = schedule.clone();
+    let opening_hours = slint::Timer::default();
+    opening_hours.start(slint::TimerMode::Repeated, SCHEDULE_INTERVAL, move || {
+        let current = checked_schedule.status();
+        let previous = status.replace(current);
+        if current == previous {
+            return;
+        }
+        log::info!("Opening hours status changed to {:?}", current);
+        match (previous, current) {
+            (Status::Closed { .. }, Status::Closed { .. }) => {}
+            (_, Status::Closed { .. }) => {
+                thread::spawn(rotate_at_closing);
             }
+            (_, Status::Open) => {
+                thread::spawn(rotate_at_opening);
+            }
+            (_, Status::Test) => {}
+        }
+        if previous == Status::Test {
+            thread::spawn(clear_test_csv);
+        }
+        let store = settings_or_defaults();
+        if let Some(ui) = ui_handle.upgrade() {
+            show_status(&ui, current, &store);
+        }
+    });
+
+    // Prompts change on their own schedule, in the selected language
+    let ui_handle = ui.as_weak();
+    let shown_prompts = prompts.clone();
+    let timer_shown_prompt = shown_prompt.clone();
+    let prompt_catalog = catalog.clone();
+    let prompt_timer = slint::Timer::default();
+    prompt_timer.start(slint::TimerMode::Repeated, SCHEDULE_INTERVAL, move || {
+        if let Some(ui) = ui_handle.upgrade() {
+            let language = ui.get_selected_language();
+            show_prompt(
+                &ui,
+                &shown_prompts,
+                &prompt_catalog,
+                language.as_str(),
+                &timer_shown_prompt,
+            );
         }
     });
-    ui.on_submit_text(|text| {
+
+    let ui_handle = ui.as_weak();
+    ui.on_submit_text(move |text| {
+        let ui = ui_handle.unwrap();
+        let text = text + keyboard.borrow_mut().flush().as_str();
+        update_keyboard(&ui, &keyboard.borrow());
+        let language = catalog
+            .get(ui.get_selected_language().as_str())
+            .unwrap_or(catalog.default_language());
+
         log::info!("Received text: {}", text);
-        submit_sentence("en", text.as_str()).unwrap();
+
+        // The closed screen covers the form, but it is only updated every few seconds
+        let status = schedule.status();
+        if matches!(status, Status::Closed { .. }) {
+            log::info!("Rejected submission outside opening hours");
+            metrics().rejected("closed");
+            let store = settings_or_defaults();
+            show_status(&ui, status, &store);
+            return;
+        }
+
+        let prompt = shown_prompt.borrow().clone();
+        let reset_after =
+            match submit_sentence(language, text.as_str(), status == Status::Test, &prompt) {
+                Ok(()) => {
+                    metrics().submission(&language.code);
+                    ui.set_submitted_text(text);
+                    ui.set_confirmation_text(language.thank_you.as_str().into());
+                    ui.set_text("".into());
+                    ui.set_text_length(0);
+                    ui.set_submit_state(SubmitState::Success);
+                    CONFIRMATION_DURATION
+                }
+                Err(e) => {
+                    log::error!("Error submitting sentence: {}", e);
+                    metrics().rejected("storage");
+                    ui.set_error_text(language.submit_error.as_str().into());
+                    ui.set_submit_state(SubmitState::Error);
+                    ERROR_DURATION
+                }
+            };
+
+        let ui_handle = ui.as_weak();
+        slint::Timer::single_shot(reset_after, move || {
+            if let Some(ui) = ui_handle.upgrade() {
+                ui.set_submit_state(SubmitState::Idle);
+            }
+        });
     });
 
     ui.window().set_fullscreen(true);
 
+    // Pinged from the event loop, so systemd restarts the program when the UI hangs
+    let watchdog = slint::Timer::default();
+    if let Some(interval) = systemd::watchdog_interval() {
+        watchdog.start(slint::TimerMode::Repeated, interval, || {
+            systemd::notify("WATCHDOG=1");
+        });
+    }
+    systemd::notify("READY=1");
+
     ui.run()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("last-snow-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn row(sentence: &str) -> Row {
+        Row {
+            language: "EN".to_string(),
+            sentence: sentence.to_string(),
+            timestamp: "2026-10-18T12:00:00+00:00".to_string(),
+            direction: Direction::Ltr,
+            test: false,
+            prompt: "weather".to_string(),
+        }
+    }
+
+    #[test]
+    fn appends_to_tmp_csv_of_older_version() {
+        let base_dir = temp_dir("outdated-csv");
+        let tmp_csv = base_dir.join("tmp.csv");
+        fs::write(
+            &tmp_csv,
+            "language,sentence,timestamp\n\
+             EN,Hello,2026-10-17T12:00:00+00:00\n\
+             PL,Cześć,2026-10-17T12:01:00+00:00\n",
+        )
+        .unwrap();
+        assert!(has_outdated_header(&tmp_csv));
+
+        assert_eq!(append_to(&tmp_csv, &row("Hi")).unwrap(), 3);
+        assert_eq!(count_csv_rows(&tmp_csv), 3);
+        let contents = fs::read_to_string(&tmp_csv).unwrap();
+        assert_eq!(contents.matches("language").count(), 1);
+
+        let rotated = rotate_tmp_csv(&base_dir).unwrap();
+        assert_eq!(rotated, base_dir.join("sentences").join("1.csv"));
+        assert_eq!(count_csv_rows(&rotated), 3);
+        assert!(!tmp_csv.exists());
+
+        assert_eq!(append_to(&tmp_csv, &row("Hi again")).unwrap(), 1);
+        assert!(!has_outdated_header(&tmp_csv));
+        assert_eq!(count_csv_rows(&tmp_csv), 1);
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn finds_rows_from_earlier_days() {
+        let base_dir = temp_dir("earlier-day");
+        let tmp_csv = base_dir.join("tmp.csv");
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert!(!started_before(&tmp_csv, date("2026-10-20")));
+
+        append_to(&tmp_csv, &row("Hello")).unwrap();
+        assert!(started_before(&tmp_csv, date("2026-10-20")));
+        assert!(!started_before(&tmp_csv, date("2026-10-10")));
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+//! `manifest.json` in the sentences folder, so TouchDesigner can tell which
+//! files are new or changed without reading them all.
+
+use crate::checksum::sha256;
+use crate::error::Result;
+use crate::health;
+use crate::metrics::metrics;
+use crate::osc;
+use crate::store::load_settings;
+use crate::{csv_reader, Row};
+use chrono::Utc;
+use rosc::OscType;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, serde::Serialize)]
+struct Entry {
+    name: String,
+    rows: usize,
+    first_timestamp: Option<String>,
+    last_timestamp: Option<String>,
+    /// Rows per language code
+    languages: BTreeMap<String, usize>,
+    sha256: String,
+    /// Why the file couldn't be read, the counts are incomplete then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Manifest {
+    updated: String,
+    files: Vec<Entry>,
+}
+
+fn count_rows(path: &Path, entry: &mut Entry) -> Result<()> {
+    entry.sha256 = sha256(path)?;
+    for row in csv_reader().from_path(path)?.deserialize::<Row>() {
+        let row = row?;
+        entry.rows += 1;
+        *entry.languages.entry(row.language).or_default() += 1;
+        if entry.first_timestamp.is_none() {
+            entry.first_timestamp = Some(row.timestamp.clone());
+        }
+        entry.last_timestamp = Some(row.timestamp);
+    }
+    Ok(())
+}
+
+/// The entry for a CSV file. Files that can't be read are listed with the
+/// error, so they don't look like they were removed.
+fn entry(path: &Path) -> Entry {
+    let mut entry = Entry {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        rows: 0,
+        first_timestamp: None,
+        last_timestamp: None,
+        languages: BTreeMap::new(),
+        sha256: String::new(),
+        error: None,
+    };
+    if let Err(e) = count_rows(path, &mut entry) {
+        log::error!("Error reading {} for the manifest: {}", path.display(), e);
+        entry.error = Some(e.to_string());
+    }
+    entry
+}
+
+/// Sorts `2.csv` before `10.csv`.
+fn index(path: &Path) -> Option<usize> {
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+/// Rewrites the manifest from the CSV files in the sentences folder. Returns its
+/// path and the number of files listed.
+pub fn update(base_dir: &Path) -> Result<(PathBuf, usize)> {
+    let sentences_dir = base_dir.join("sentences");
+    fs::create_dir_all(&sentences_dir)?;
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&sentences_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+        .collect();
+    files.sort_by_key(|path| (index(path), path.clone()));
+
+    let entries: Vec<Entry> = files.iter().map(|file| entry(file)).collect();
+    let count = entries.len();
+    let manifest = Manifest {
+        updated: Utc::now().to_rfc3339(),
+        files: entries,
+    };
+
+    // Written aside and renamed, so readers never see half a manifest
+    let path = sentences_dir.join(MANIFEST_FILE);
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(&manifest)?)?;
+    fs::rename(&tmp_path, &path)?;
+    Ok((path, count))
+}
+
+/// Updates the manifest and sends `/manifest` with its path in the public dir
+/// and the number of files to the heartbeat destinations.
+pub fn update_and_announce(base_dir: &Path) {
+    let (path, files) = match update(base_dir) {
+        Ok(updated) => updated,
+        Err(e) => {
+            log::error!("Error updating the manifest: {}", e);
+            return;
+        }
+    };
+    log::info!("Updated {} with {} files", path.display(), files);
+
+    // Receivers mount the share somewhere else, so only the part in it is sent
+    let shared_path = path.strip_prefix(base_dir).unwrap_or(&path);
+    let shared_path = shared_path.to_string_lossy().replace('\\', "/");
+
+    let store = match load_settings() {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!("Error sending /manifest: {}", e);
+            return;
+        }
+    };
+    let packet = osc::message(
+        "/manifest",
+        vec![OscType::String(shared_path), OscType::Int(files as i32)],
+    );
+    for dest in health::destinations(&store) {
+        osc::send(&dest, &packet).unwrap_or_else(|e| {
+            log::error!("Error sending /manifest to {}: {}", dest, e);
+            metrics().osc_send_failure();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn lists_unreadable_files_with_error() {
+        let base_dir = env::temp_dir().join(format!("last-snow-manifest-{}", process::id()));
+        fs::remove_dir_all(&base_dir).ok();
+        let sentences_dir = base_dir.join("sentences");
+        fs::create_dir_all(&sentences_dir).unwrap();
+        fs::write(
+            sentences_dir.join("1.csv"),
+            "language,sentence,timestamp\nEN,Hello,2026-10-18T12:00:00+00:00\n",
+        )
+        .unwrap();
+        fs::write(
+            sentences_dir.join("2.csv"),
+            b"language,sentence,timestamp\nEN,\xff\xfe,2026-10-18T12:00:00+00:00\n",
+        )
+        .unwrap();
+
+        let (path, count) = update(&base_dir).unwrap();
+        assert_eq!(path, sentences_dir.join(MANIFEST_FILE));
+        assert_eq!(count, 2);
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let files = manifest["files"].as_array().unwrap();
+        assert_eq!(files[0]["rows"], 1);
+        assert!(files[0].get("error").is_none());
+        assert_eq!(files[1]["name"], "2.csv");
+        assert!(files[1]["error"].is_string());
+    }
+}
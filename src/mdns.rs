@@ -0,0 +1,126 @@
+//! The mDNS responder, shared by everything that finds or announces services
+//! on the local network.
+
+use crate::store::{load_settings, Store};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+
+pub const OSC_SERVICE: &str = "_osc._udp.local.";
+pub const HTTP_SERVICE: &str = "_http._tcp.local.";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+static DAEMON: OnceLock<Option<ServiceDaemon>> = OnceLock::new();
+/// The full name of each advertised service, by type
+static ADVERTISED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Started on first use, `None` if that failed.
+pub fn daemon() -> Option<&'static ServiceDaemon> {
+    DAEMON
+        .get_or_init(|| {
+            ServiceDaemon::new()
+                .map_err(|e| log::error!("Error starting mDNS: {}", e))
+                .ok()
+        })
+        .as_ref()
+}
+
+/// The name of this machine, without `.local`.
+pub fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "last-snow".to_string())
+}
+
+/// The `installation_name` from settings, or the host name if it isn't set.
+pub fn installation_name(store: &Store) -> String {
+    store
+        .get("installation_name")
+        .and_then(|val| val.as_str())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(hostname)
+}
+
+/// Announces a service of this kiosk on `port` over DNS-SD, with the
+/// installation name and version in its TXT record. Announcing the same type
+/// again replaces the earlier one, i.e. when the port changes.
+pub fn advertise(service_type: &str, port: u16, properties: &[(&str, &str)]) {
+    let store = match load_settings() {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!("Error advertising {}: {}", service_type, e);
+            return;
+        }
+    };
+    if !store
+        .get("mdns_advertise")
+        .and_then(|val| val.as_bool())
+        .unwrap_or(true)
+    {
+        return;
+    }
+    let Some(daemon) = daemon() else {
+        return;
+    };
+
+    let hostname = hostname();
+    let installation = installation_name(&store);
+    let mut txt = vec![
+        ("installation", installation.as_str()),
+        ("version", VERSION),
+    ];
+    txt.extend_from_slice(properties);
+
+    // Named by port too, so instances on one machine don't replace each other
+    let service = match ServiceInfo::new(
+        service_type,
+        &format!("{}-{}", hostname, port),
+        &format!("{}.local.", hostname),
+        (),
+        port,
+        &txt[..],
+    ) {
+        Ok(service) => service.enable_addr_auto(),
+        Err(e) => {
+            log::error!("Error advertising {}: {}", service_type, e);
+            return;
+        }
+    };
+
+    let mut advertised = ADVERTISED.lock().unwrap_or_else(|e| e.into_inner());
+    let fullname = service.get_fullname().to_string();
+    if let Some(previous) = advertised
+        .get(service_type)
+        .filter(|name| **name != fullname)
+    {
+        daemon.unregister(previous).map(drop).unwrap_or_else(|e| {
+            log::error!("Error withdrawing {}: {}", previous, e);
+        });
+    }
+    match daemon.register(service) {
+        Ok(()) => {
+            log::info!("Advertising {} on port {}", service_type, port);
+            advertised.insert(service_type.to_string(), fullname);
+        }
+        Err(e) => log::error!("Error advertising {}: {}", service_type, e),
+    }
+}
+
+/// Stops announcing the service of `service_type`, if it was announced.
+pub fn withdraw(service_type: &str) {
+    let mut advertised = ADVERTISED.lock().unwrap_or_else(|e| e.into_inner());
+    let (Some(fullname), Some(daemon)) = (advertised.remove(service_type), daemon()) else {
+        return;
+    };
+    log::info!("No longer advertising {}", service_type);
+    daemon.unregister(&fullname).map(drop).unwrap_or_else(|e| {
+        log::error!("Error withdrawing {}: {}", fullname, e);
+    });
+}
@@ -0,0 +1,236 @@
+//! `last-snow merge`: combines the sentences of several kiosks into one dataset,
+//! with a column saying which kiosk each row came from.
+
+use crate::error::{Error, Result};
+use crate::languages::Direction;
+use crate::manifest::MANIFEST_FILE;
+use crate::{csv_reader, Row};
+use chrono::DateTime;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(Error::InvalidArgument(format!(
+                "Unknown format {}, use csv or json",
+                format
+            ))),
+        }
+    }
+
+    /// Guesses the format from the output file extension, CSV by default.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Format::Json,
+            _ => Format::Csv,
+        }
+    }
+}
+
+/// A kiosk's sentences: a `sentences` folder, a single CSV file or a `.tar.zst`
+/// archive written by the archiver.
+#[derive(Debug)]
+pub struct Input {
+    pub kiosk: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct MergedRow {
+    kiosk: String,
+    language: String,
+    sentence: String,
+    timestamp: String,
+    direction: Direction,
+    prompt: String,
+}
+
+fn read_csv(reader: impl Read, kiosk: &str, rows: &mut Vec<MergedRow>) -> Result<()> {
+    for row in csv_reader().from_reader(reader).deserialize::<Row>() {
+        let row = row?;
+        if row.test {
+            continue;
+        }
+        rows.push(MergedRow {
+            kiosk: kiosk.to_string(),
+            language: row.language,
+            sentence: row.sentence,
+            timestamp: row.timestamp,
+            direction: row.direction,
+            prompt: row.prompt,
+        });
+    }
+    Ok(())
+}
+
+fn is_archive(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".tar.zst")
+}
+
+fn read_archive(path: &Path, kiosk: &str, rows: &mut Vec<MergedRow>) -> Result<()> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let is_csv = entry.path()?.extension().is_some_and(|ext| ext == "csv");
+        if is_csv {
+            read_csv(entry, kiosk, rows)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_input(input: &Input, rows: &mut Vec<MergedRow>) -> Result<()> {
+    let path = &input.path;
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let file = entry?.path();
+            if file.file_name().is_some_and(|name| name == MANIFEST_FILE) {
+                continue;
+            }
+            if is_archive(&file) {
+                read_archive(&file, &input.kiosk, rows)?;
+            } else if file.extension().is_some_and(|ext| ext == "csv") {
+                read_csv(File::open(&file)?, &input.kiosk, rows)?;
+            }
+        }
+    } else if is_archive(path) {
+        read_archive(path, &input.kiosk, rows)?;
+    } else {
+        read_csv(File::open(path)?, &input.kiosk, rows)?;
+    }
+    Ok(())
+}
+
+/// Reads every input, drops rows with the same language, sentence and timestamp
+/// as an earlier one, and writes the rest sorted by time.
+pub fn run(inputs: &[Input], output: &Path, format: Format) -> Result<usize> {
+    let mut rows = Vec::new();
+    for input in inputs {
+        let before = rows.len();
+        read_input(input, &mut rows)?;
+        eprintln!(
+            "Read {} rows from {} ({})",
+            rows.len() - before,
+            input.path.display(),
+            input.kiosk
+        );
+    }
+
+    // Earlier inputs win, so the same archive read twice keeps its first kiosk ID
+    let mut seen = HashSet::new();
+    rows.retain(|row| {
+        seen.insert((
+            row.language.clone(),
+            row.sentence.clone(),
+            row.timestamp.clone(),
+        ))
+    });
+    rows.sort_by(|a, b| {
+        let time = |row: &MergedRow| DateTime::parse_from_rfc3339(&row.timestamp).ok();
+        time(a)
+            .cmp(&time(b))
+            .then_with(|| a.timestamp.cmp(&b.timestamp))
+            .then_with(|| a.kiosk.cmp(&b.kiosk))
+    });
+
+    let writer: Box<dyn Write> = if output == Path::new("-") {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(output)?)
+    };
+    match format {
+        Format::Csv => {
+            let mut wtr = csv::Writer::from_writer(writer);
+            for row in &rows {
+                wtr.serialize(row)?;
+            }
+            wtr.flush()?;
+        }
+        Format::Json => {
+            let mut writer = writer;
+            serde_json::to_writer_pretty(&mut writer, &rows)?;
+            writer.flush()?;
+        }
+    }
+
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("last-snow-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn merges_kiosks_in_time_order() {
+        let dir = temp_dir("merge");
+        let sentences = dir.join("a").join("sentences");
+        fs::create_dir_all(&sentences).unwrap();
+        fs::write(
+            sentences.join("1.csv"),
+            "language,sentence,timestamp,direction,test,prompt\n\
+             EN,Hello,2026-10-17T12:00:00+02:00,ltr,false,\n\
+             EN,Testing,2026-10-17T09:00:00+00:00,ltr,true,\n\
+             PL,Cześć,2026-10-17T11:00:00+00:00,ltr,false,p1\n",
+        )
+        .unwrap();
+        fs::write(sentences.join(MANIFEST_FILE), "{}").unwrap();
+        let b = dir.join("b.csv");
+        fs::write(
+            &b,
+            "language,sentence,timestamp,direction,test,prompt\n\
+             EN,Hello,2026-10-17T12:00:00+02:00,ltr,false,\n\
+             DE,Hallo,2026-10-17T10:30:00+00:00,ltr,false,\n",
+        )
+        .unwrap();
+
+        let inputs = [
+            Input {
+                kiosk: "a".to_string(),
+                path: sentences,
+            },
+            Input {
+                kiosk: "b".to_string(),
+                path: b,
+            },
+        ];
+        let output = dir.join("merged.json");
+        assert_eq!(run(&inputs, &output, Format::Json).unwrap(), 3);
+
+        let merged: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        let rows: Vec<(&str, &str)> = merged
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                (
+                    row["kiosk"].as_str().unwrap(),
+                    row["sentence"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        // 12:00+02:00 is the earliest even though it sorts last as text
+        assert_eq!(rows, vec![("a", "Hello"), ("b", "Hallo"), ("a", "Cześć")]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
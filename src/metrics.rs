@@ -0,0 +1,281 @@
+//! Counters for the Prometheus `/metrics` endpoint.
+
+use crate::count_csv_rows;
+use crate::mdns;
+use crate::paths::public_dir;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+pub struct Metrics {
+    submissions: Mutex<BTreeMap<String, u64>>,
+    rejected: Mutex<BTreeMap<String, u64>>,
+    last_submission: AtomicU64,
+    osc_received: AtomicU64,
+    osc_invalid: AtomicU64,
+    osc_rejected: AtomicU64,
+    osc_replies: AtomicU64,
+    osc_send_failures: AtomicU64,
+    csv_rotated: AtomicU64,
+    restarts: AtomicU64,
+}
+
+fn started() -> Instant {
+    static STARTED: OnceLock<Instant> = OnceLock::new();
+    *STARTED.get_or_init(Instant::now)
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    started();
+    METRICS.get_or_init(Metrics::default)
+}
+
+/// Escapes a label value for the text exposition format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn increment(counters: &Mutex<BTreeMap<String, u64>>, label: &str) {
+    if let Ok(mut counters) = counters.lock() {
+        *counters.entry(label.to_string()).or_default() += 1;
+    }
+}
+
+impl Metrics {
+    /// Starts the per-language counters at zero so idle languages are reported too.
+    pub fn init_languages<'a>(&self, codes: impl Iterator<Item = &'a str>) {
+        if let Ok(mut submissions) = self.submissions.lock() {
+            for code in codes {
+                submissions.entry(code.to_string()).or_default();
+            }
+        }
+    }
+
+    pub fn submission(&self, language: &str) {
+        increment(&self.submissions, language);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.last_submission.store(now, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self, reason: &str) {
+        increment(&self.rejected, reason);
+    }
+
+    pub fn osc_received(&self) {
+        self.osc_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn osc_invalid(&self) {
+        self.osc_invalid.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn osc_rejected(&self) {
+        self.osc_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn osc_reply(&self) {
+        self.osc_replies.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn osc_send_failure(&self) {
+        self.osc_send_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn csv_rotated(&self) {
+        self.csv_rotated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Restarts by the supervisor before this process, see [`crate::supervisor`].
+    pub fn set_restarts(&self, restarts: u64) {
+        self.restarts.store(restarts, Ordering::Relaxed);
+    }
+
+    /// Unix time of the last submission, 0 if there was none since startup.
+    pub fn last_submission(&self) -> u64 {
+        self.last_submission.load(Ordering::Relaxed)
+    }
+
+    pub fn uptime(&self) -> u64 {
+        started().elapsed().as_secs()
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let mut metric = |name: &str, help: &str, kind: &str, samples: Vec<(String, u64)>| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+
+        let labelled =
+            |counters: &Mutex<BTreeMap<String, u64>>, label: &str| -> Vec<(String, u64)> {
+                counters
+                    .lock()
+                    .map(|counters| {
+                        counters
+                            .iter()
+                            .map(|(value, count)| {
+                                let labels = format!("{{{}=\"{}\"}}", label, escape_label(value));
+                                (labels, *count)
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+
+        let base_dir = public_dir().ok();
+        let tmp_rows = base_dir
+            .as_ref()
+            .map(|dir| count_csv_rows(&dir.join("tmp.csv")) as u64)
+            .unwrap_or_default();
+        let disk_free = base_dir
+            .as_ref()
+            .and_then(|dir| fs2::available_space(dir).ok())
+            .unwrap_or_default();
+
+        metric(
+            "last_snow_submissions_total",
+            "Sentences submitted, per language.",
+            "counter",
+            labelled(&self.submissions, "language"),
+        );
+        metric(
+            "last_snow_submissions_rejected_total",
+            "Submissions that could not be accepted, per reason.",
+            "counter",
+            labelled(&self.rejected, "reason"),
+        );
+        metric(
+            "last_snow_last_submission_timestamp_seconds",
+            "Unix time of the last submission, 0 if none since startup.",
+            "gauge",
+            vec![(String::new(), self.last_submission())],
+        );
+        metric(
+            "last_snow_osc_packets_received_total",
+            "OSC packets received.",
+            "counter",
+            vec![(String::new(), self.osc_received.load(Ordering::Relaxed))],
+        );
+        metric(
+            "last_snow_osc_packets_invalid_total",
+            "OSC packets that could not be decoded or had an unknown address.",
+            "counter",
+            vec![(String::new(), self.osc_invalid.load(Ordering::Relaxed))],
+        );
+        metric(
+            "last_snow_osc_packets_rejected_total",
+            "OSC commands rejected by the source allowlist or the password.",
+            "counter",
+            vec![(String::new(), self.osc_rejected.load(Ordering::Relaxed))],
+        );
+        metric(
+            "last_snow_osc_replies_received_total",
+            "OSC replies and announcements received, which are not answered.",
+            "counter",
+            vec![(String::new(), self.osc_replies.load(Ordering::Relaxed))],
+        );
+        metric(
+            "last_snow_osc_send_failures_total",
+            "OSC messages that could not be sent.",
+            "counter",
+            vec![(
+                String::new(),
+                self.osc_send_failures.load(Ordering::Relaxed),
+            )],
+        );
+        metric(
+            "last_snow_tmp_csv_rows",
+            "Rows currently in tmp.csv.",
+            "gauge",
+            vec![(String::new(), tmp_rows)],
+        );
+        metric(
+            "last_snow_csv_rotated_total",
+            "Times tmp.csv was moved to the sentences folder.",
+            "counter",
+            vec![(String::new(), self.csv_rotated.load(Ordering::Relaxed))],
+        );
+        metric(
+            "last_snow_public_dir_free_bytes",
+            "Free disk space on the public dir.",
+            "gauge",
+            vec![(String::new(), disk_free)],
+        );
+        metric(
+            "last_snow_restarts_total",
+            "Times the supervisor restarted the program after a crash.",
+            "counter",
+            vec![(String::new(), self.restarts.load(Ordering::Relaxed))],
+        );
+        metric(
+            "last_snow_uptime_seconds",
+            "Seconds since the program started.",
+            "gauge",
+            vec![(String::new(), self.uptime())],
+        );
+
+        out
+    }
+}
+
+/// Serves `/metrics` on the given port in a background thread.
+pub fn serve(port: u16) {
+    let server = match tiny_http::Server::http(("0.0.0.0", port)) {
+        Ok(server) => server,
+        Err(e) => {
+            log::error!("Error starting metrics server on port {}: {}", port, e);
+            return;
+        }
+    };
+    log::info!("Serving metrics on port {}", port);
+    mdns::advertise(mdns::HTTP_SERVICE, port, &[("path", "/metrics")]);
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let content_type = tiny_http::Header::from_bytes(
+                    &b"Content-Type"[..],
+                    &b"text/plain; version=0.0.4"[..],
+                )
+                .expect("valid header");
+                tiny_http::Response::from_string(metrics().render()).with_header(content_type)
+            } else {
+                tiny_http::Response::from_string("Not found").with_status_code(404)
+            };
+            request.respond(response).unwrap_or_else(|e| {
+                log::error!("Error responding to metrics request: {}", e);
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_label_values() {
+        let metrics = Metrics::default();
+        metrics.submission("EN");
+        metrics.submission("a\\b\"c\nd");
+        let rendered = metrics.render();
+        assert!(rendered.contains("last_snow_submissions_total{language=\"EN\"} 1\n"));
+        assert!(rendered.contains("last_snow_submissions_total{language=\"a\\\\b\\\"c\\nd\"} 1\n"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Reading command arguments, converting between OSC types where the value
+//! allows it, since senders like TouchDesigner often send numbers as floats.
+
+use crate::error::{Error, Result};
+use rosc::OscType;
+
+/// Fails unless there are exactly `expected` arguments.
+pub fn count(args: &[OscType], expected: usize) -> Result<()> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "Expected {} argument{}, got {}",
+            expected,
+            if expected == 1 { "" } else { "s" },
+            args.len()
+        )))
+    }
+}
+
+fn get(args: &[OscType], index: usize) -> Result<&OscType> {
+    args.get(index)
+        .ok_or_else(|| Error::InvalidArgument(format!("Missing argument {}", index + 1)))
+}
+
+fn whole(value: f64) -> Option<i64> {
+    (value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64)
+        .then_some(value as i64)
+}
+
+/// Reads an integer, also accepting whole floats like `200.0` and numeric strings.
+pub fn int<T: TryFrom<i64>>(args: &[OscType], index: usize) -> Result<T> {
+    let arg = get(args, index)?;
+    let value = match arg {
+        OscType::Int(value) => Some(*value as i64),
+        OscType::Long(value) => Some(*value),
+        OscType::Float(value) => whole(*value as f64),
+        OscType::Double(value) => whole(*value),
+        OscType::String(value) => {
+            let value = value.trim();
+            value
+                .parse::<i64>()
+                .ok()
+                .or_else(|| value.parse::<f64>().ok().and_then(whole))
+        }
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error::InvalidArgument(format!(
+            "Argument {} must be an integer, got {}",
+            index + 1,
+            describe(arg)
+        ))
+    })?;
+
+    T::try_from(value).map_err(|_| {
+        Error::InvalidArgument(format!("Argument {} is out of range: {}", index + 1, value))
+    })
+}
+
+/// Reads a string, also accepting numbers.
+pub fn string(args: &[OscType], index: usize) -> Result<String> {
+    match get(args, index)? {
+        OscType::String(value) => Ok(value.clone()),
+        OscType::Int(value) => Ok(value.to_string()),
+        OscType::Long(value) => Ok(value.to_string()),
+        OscType::Float(value) => Ok(value.to_string()),
+        OscType::Double(value) => Ok(value.to_string()),
+        arg => Err(Error::InvalidArgument(format!(
+            "Argument {} must be a string, got {}",
+            index + 1,
+            describe(arg)
+        ))),
+    }
+}
+
+fn describe(arg: &OscType) -> String {
+    match arg {
+        OscType::Int(value) => format!("int {}", value),
+        OscType::Long(value) => format!("long {}", value),
+        OscType::Float(value) => format!("float {}", value),
+        OscType::Double(value) => format!("double {}", value),
+        OscType::String(value) => format!("string \"{}\"", value),
+        OscType::Bool(value) => format!("bool {}", value),
+        OscType::Nil => "nil".to_string(),
+        OscType::Inf => "infinitum".to_string(),
+        OscType::Blob(_) => "blob".to_string(),
+        _ => format!("{:?}", arg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_whole_numbers_of_any_type() {
+        for arg in [
+            OscType::Int(200),
+            OscType::Long(200),
+            OscType::Float(200.0),
+            OscType::Double(200.0),
+            OscType::String("200".to_string()),
+            OscType::String(" 200.0 ".to_string()),
+        ] {
+            assert_eq!(
+                int::<u32>(std::slice::from_ref(&arg), 0).unwrap(),
+                200,
+                "{:?}",
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_fractions_and_other_types() {
+        for arg in [
+            OscType::Float(200.5),
+            OscType::Double(f64::NAN),
+            OscType::Double(f64::INFINITY),
+            OscType::String("two hundred".to_string()),
+            OscType::Bool(true),
+            OscType::Nil,
+        ] {
+            assert!(
+                int::<i64>(std::slice::from_ref(&arg), 0).is_err(),
+                "{:?}",
+                arg
+            );
+        }
+        assert!(int::<i64>(&[], 0).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        assert!(int::<u8>(&[OscType::Int(256)], 0).is_err());
+        assert!(int::<u32>(&[OscType::Int(-1)], 0).is_err());
+        assert!(int::<i64>(&[OscType::Double(9.3e18)], 0).is_err());
+        assert!(int::<i64>(&[OscType::Double(i64::MAX as f64)], 0).is_err());
+        assert_eq!(
+            int::<i64>(&[OscType::Double(-9e18)], 0).unwrap(),
+            -9e18 as i64
+        );
+    }
+
+    #[test]
+    fn reads_numbers_as_strings() {
+        assert_eq!(string(&[OscType::Int(7)], 0).unwrap(), "7");
+        assert_eq!(string(&[OscType::Float(0.5)], 0).unwrap(), "0.5");
+        assert!(string(&[OscType::Nil], 0).is_err());
+        assert!(count(&[OscType::Nil], 2).is_err());
+        assert!(count(&[OscType::Nil], 1).is_ok());
+    }
+}
@@ -0,0 +1,419 @@
+//! Access control for OSC commands: `osc_allowed_sources` limits who can send
+//! anything, and when `osc_password` is set, commands other than `/health` must
+//! be signed with it. A signed command ends with two more arguments: the Unix
+//! time it was sent and the hex HMAC-SHA256 of the command up to and including
+//! that time, OSC encoded, keyed with the password. Messages between the
+//! kiosks of a cluster are signed the same way with `cluster_secret`.
+
+use crate::error::{Error, Result};
+use crate::metrics::metrics;
+use crate::store::Store;
+use hmac::{Hmac, Mac};
+use rosc::{OscMessage, OscPacket, OscType};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Commands that don't need a signature.
+const OPEN_COMMANDS: [&str; 1] = ["/health"];
+/// How far the time of a signed command may be off, and how long its
+/// signature is remembered so it can't be sent again.
+const SIGNATURE_WINDOW: Duration = Duration::from_secs(30);
+/// Failed signatures from one address before it is locked out.
+const MAX_FAILURES: usize = 5;
+/// How long failures are counted, and how long a locked out address stays so.
+const LOCKOUT: Duration = Duration::from_secs(60);
+
+/// Signatures of the commands accepted within the window.
+static SEEN: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+/// Recent failed signatures per address.
+static FAILURES: Mutex<BTreeMap<IpAddr, Vec<Instant>>> = Mutex::new(BTreeMap::new());
+
+/// An address with an optional prefix length, i.e. `192.168.0.0/24` or `fe80::/10`.
+#[derive(Debug, PartialEq)]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+fn width(addr: &IpAddr) -> u32 {
+    if addr.is_ipv4() {
+        32
+    } else {
+        128
+    }
+}
+
+fn bits(addr: &IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(*addr) as u128,
+        IpAddr::V6(addr) => u128::from(*addr),
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgument(format!("Invalid address or subnet {}", s));
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr = IpAddr::from_str(addr)
+            .map_err(|_| invalid())?
+            .to_canonical();
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| invalid())?,
+            None => width(&addr),
+        };
+        if prefix > width(&addr) {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl Subnet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Dual stack sockets report IPv4 peers as `::ffff:a.b.c.d`
+        let ip = ip.to_canonical();
+        if self.addr.is_ipv4() != ip.is_ipv4() {
+            return false;
+        }
+        if self.prefix == 0 {
+            return true;
+        }
+        let width = width(&ip);
+        let mask = (u128::MAX >> (128 - width)) & (u128::MAX << (width - self.prefix));
+        bits(&self.addr) & mask == bits(&ip) & mask
+    }
+}
+
+/// Whether `ip` is in `osc_allowed_sources`, an empty or missing list allows everyone.
+fn source_allowed(store: &Store, ip: IpAddr) -> bool {
+    let Some(sources) = store
+        .get("osc_allowed_sources")
+        .and_then(|val| val.as_array())
+        .filter(|sources| !sources.is_empty())
+    else {
+        return true;
+    };
+
+    sources
+        .iter()
+        .filter_map(|source| source.as_str())
+        .filter_map(|source| {
+            Subnet::from_str(source)
+                .map_err(|e| log::error!("Error in osc_allowed_sources: {}", e))
+                .ok()
+        })
+        .any(|subnet| subnet.contains(ip))
+}
+
+fn password(store: &Store) -> Option<&str> {
+    store
+        .get("osc_password")
+        .and_then(|val| val.as_str())
+        .filter(|password| !password.is_empty())
+}
+
+/// Compares without stopping at the first difference, so the time taken doesn't
+/// tell how much of a guess was right.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// Hex encoded HMAC-SHA256 of `bytes`, keyed with `secret`.
+fn sign(secret: &str, bytes: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(bytes);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// Whether `signature` is the HMAC of `bytes` keyed with `secret`.
+fn verify(secret: &str, bytes: &[u8], signature: &str) -> bool {
+    same(&sign(secret, bytes), &signature.to_ascii_lowercase())
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Whether `time`, in Unix seconds, is within the window around now.
+fn is_recent(time: i64) -> bool {
+    unix_time().abs_diff(time) <= SIGNATURE_WINDOW.as_secs()
+}
+
+/// Remembers `signature`, failing if it was seen within the window before.
+fn first_use(signature: &str) -> bool {
+    let mut seen = SEEN.lock().unwrap_or_else(|e| e.into_inner());
+    // Twice the window, as the time may be that far off in either direction
+    seen.retain(|_, used| used.elapsed() < SIGNATURE_WINDOW * 2);
+    seen.insert(signature.to_ascii_lowercase(), Instant::now())
+        .is_none()
+}
+
+fn locked_out(ip: IpAddr) -> bool {
+    let mut failures = FAILURES.lock().unwrap_or_else(|e| e.into_inner());
+    failures.retain(|_, times| {
+        times.retain(|time| time.elapsed() < LOCKOUT);
+        !times.is_empty()
+    });
+    failures
+        .get(&ip)
+        .is_some_and(|times| times.len() >= MAX_FAILURES)
+}
+
+fn reject(ip: IpAddr, addr: &str, reason: &str) -> Error {
+    log::warn!("Rejected {} from {}: {}", addr, ip, reason);
+    metrics().osc_rejected();
+    Error::Unauthorized(reason.to_string())
+}
+
+/// Like `reject`, and counts the failure towards locking `ip` out.
+fn fail(ip: IpAddr, addr: &str, reason: &str) -> Error {
+    FAILURES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(ip)
+        .or_default()
+        .push(Instant::now());
+    reject(ip, addr, reason)
+}
+
+/// Checks the signature and time of a message, returning it without them, and
+/// the signature.
+fn check_signature(
+    password: &str,
+    mut msg: OscMessage,
+    ip: IpAddr,
+) -> Result<(OscMessage, String)> {
+    if msg.args.len() < 2 {
+        return Err(fail(ip, &msg.addr, "Not signed"));
+    }
+    let Some(OscType::String(signature)) = msg.args.pop() else {
+        return Err(fail(ip, &msg.addr, "The signature must be a string"));
+    };
+    let signed = rosc::encoder::encode(&OscPacket::Message(msg.clone()))?;
+    if !verify(password, &signed, &signature) {
+        return Err(fail(ip, &msg.addr, "Wrong signature"));
+    }
+
+    let time = super::args::int::<i64>(&msg.args, msg.args.len() - 1)
+        .map_err(|_| fail(ip, &msg.addr, "The time must be an integer"))?;
+    if !is_recent(time) {
+        return Err(reject(
+            ip,
+            &msg.addr,
+            "Too old or too far ahead, check the clocks",
+        ));
+    }
+    msg.args.pop();
+    Ok((msg, signature))
+}
+
+/// Checks whether `ip` may send the command, and returns it without the
+/// signature arguments.
+pub fn authorize(store: &Store, msg: OscMessage, ip: IpAddr) -> Result<OscMessage> {
+    let ip = ip.to_canonical();
+    if !source_allowed(store, ip) {
+        return Err(reject(ip, &msg.addr, "Source not allowed"));
+    }
+    if OPEN_COMMANDS.contains(&msg.addr.as_str()) {
+        return Ok(msg);
+    }
+    let Some(password) = password(store) else {
+        return Ok(msg);
+    };
+    if locked_out(ip) {
+        return Err(reject(
+            ip,
+            &msg.addr,
+            "Too many failed signatures, try again later",
+        ));
+    }
+    let (msg, signature) = check_signature(password, msg, ip)?;
+    if !first_use(&signature) {
+        return Err(reject(ip, &msg.addr, "Already received"));
+    }
+    Ok(msg)
+}
+
+/// Appends the time and the signature with `secret` that [`authorize_peer`]
+/// checks.
+pub fn sign_message(secret: &str, mut msg: OscMessage) -> Result<OscMessage> {
+    msg.args.push(OscType::Long(unix_time()));
+    let signed = rosc::encoder::encode(&OscPacket::Message(msg.clone()))?;
+    msg.args.push(OscType::String(sign(secret, &signed)));
+    Ok(msg)
+}
+
+/// Checks a message from another kiosk of the cluster against
+/// `osc_allowed_sources` and its signature with `secret`, and returns it
+/// without the signature arguments. Unlike commands, a message may come twice:
+/// every cluster message can be applied again without effect.
+pub fn authorize_peer(
+    store: &Store,
+    secret: &str,
+    msg: OscMessage,
+    ip: IpAddr,
+) -> Result<OscMessage> {
+    let ip = ip.to_canonical();
+    if !source_allowed(store, ip) {
+        return Err(reject(ip, &msg.addr, "Source not allowed"));
+    }
+    if locked_out(ip) {
+        return Err(reject(
+            ip,
+            &msg.addr,
+            "Too many failed signatures, try again later",
+        ));
+    }
+    check_signature(secret, msg, ip).map(|(msg, _)| msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::StoreBuilder;
+    use serde_json::json;
+
+    fn store(password: &str) -> Store {
+        let mut store = StoreBuilder::new(".settings".into()).build();
+        store
+            .insert("osc_password".to_string(), json!(password))
+            .unwrap();
+        store
+    }
+
+    fn now() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+    }
+
+    fn signed(secret: &str, addr: &str, mut args: Vec<OscType>, time: i64) -> OscMessage {
+        args.push(OscType::Long(time));
+        let mut msg = OscMessage {
+            addr: addr.to_string(),
+            args,
+        };
+        let bytes = rosc::encoder::encode(&OscPacket::Message(msg.clone())).unwrap();
+        msg.args.push(OscType::String(sign(secret, &bytes)));
+        msg
+    }
+
+    fn unsigned(addr: &str) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    // Each test uses its own addresses, the failures are counted globally
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 7, last])
+    }
+
+    fn subnet(s: &str) -> Subnet {
+        Subnet::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn matches_subnets() {
+        let lan = subnet("192.168.1.0/24");
+        assert!(lan.contains(IpAddr::from([192, 168, 1, 20])));
+        assert!(!lan.contains(IpAddr::from([192, 168, 2, 20])));
+        assert!(!lan.contains("fe80::1".parse().unwrap()));
+
+        let host = subnet("192.168.1.20/32");
+        assert!(host.contains(IpAddr::from([192, 168, 1, 20])));
+        assert!(!host.contains(IpAddr::from([192, 168, 1, 21])));
+        assert!(subnet("192.168.1.20").contains(IpAddr::from([192, 168, 1, 20])));
+
+        assert!(subnet("0.0.0.0/0").contains(IpAddr::from([10, 1, 2, 3])));
+        assert!(!subnet("0.0.0.0/0").contains("::1".parse().unwrap()));
+        assert!(subnet("::/0").contains("2001:db8::1".parse().unwrap()));
+        assert!(subnet("fd00::/8").contains("fd12::1".parse().unwrap()));
+        assert!(subnet("::1/128").contains("::1".parse().unwrap()));
+
+        assert!(Subnet::from_str("192.168.1.0/33").is_err());
+        assert!(Subnet::from_str("kiosk.local").is_err());
+    }
+
+    #[test]
+    fn matches_v4_mapped_addresses() {
+        let mapped: IpAddr = "::ffff:192.168.1.20".parse().unwrap();
+        assert!(subnet("192.168.1.0/24").contains(mapped));
+        assert!(subnet("::ffff:192.168.1.20").contains(IpAddr::from([192, 168, 1, 20])));
+        assert!(!subnet("::/0").contains(mapped));
+    }
+
+    #[test]
+    fn accepts_signed_command_once() {
+        let store = store("secret");
+        let msg = signed("secret", "/max_characters", vec![OscType::Int(200)], now());
+        let accepted = authorize(&store, msg.clone(), ip(1)).unwrap();
+        assert_eq!(accepted.args, vec![OscType::Int(200)]);
+        assert!(authorize(&store, msg, ip(1)).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_old_and_missing_signatures() {
+        let store = store("secret");
+        let wrong = signed("guess", "/remove_all_csv", Vec::new(), now());
+        assert!(authorize(&store, wrong, ip(2)).is_err());
+        let old = signed("secret", "/remove_all_csv", Vec::new(), now() - 120);
+        assert!(authorize(&store, old, ip(2)).is_err());
+        assert!(authorize(&store, unsigned("/remove_all_csv"), ip(2)).is_err());
+        assert!(authorize(&store, unsigned("/health"), ip(2)).is_ok());
+    }
+
+    #[test]
+    fn locks_out_after_failed_signatures() {
+        let store = store("secret");
+        for _ in 0..MAX_FAILURES {
+            let wrong = signed("guess", "/remove_all_csv", Vec::new(), now());
+            assert!(authorize(&store, wrong, ip(3)).is_err());
+        }
+        let right = signed("secret", "/remove_all_csv", Vec::new(), now());
+        assert!(authorize(&store, right.clone(), ip(3)).is_err());
+        assert!(authorize(&store, right, ip(4)).is_ok());
+    }
+
+    #[test]
+    fn accepts_signed_peer_messages_again() {
+        let store = store("");
+        let msg = OscMessage {
+            addr: "/cluster/hello".to_string(),
+            args: vec![OscType::String("kiosks".to_string())],
+        };
+        let signed = sign_message("cluster secret", msg.clone()).unwrap();
+        assert_eq!(
+            authorize_peer(&store, "cluster secret", signed.clone(), ip(6)).unwrap(),
+            msg
+        );
+        assert!(authorize_peer(&store, "cluster secret", signed.clone(), ip(6)).is_ok());
+        assert!(authorize_peer(&store, "other secret", signed, ip(6)).is_err());
+        assert!(authorize_peer(&store, "cluster secret", msg, ip(6)).is_err());
+    }
+
+    #[test]
+    fn accepts_unsigned_commands_without_password() {
+        let store = store("");
+        assert!(authorize(&store, unsigned("/remove_all_csv"), ip(5)).is_ok());
+    }
+}
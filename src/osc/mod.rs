@@ -0,0 +1,333 @@
+pub mod args;
+pub mod auth;
+mod slip;
+pub mod tcp;
+pub mod websocket;
+
+use crate::error::{Error, Result};
+use crate::metrics::metrics;
+use rosc::{OscMessage, OscPacket, OscType};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::WebSocket;
+
+pub const DEFAULT_LISTEN_HOST: &str = "0.0.0.0";
+/// The default before the OSC service was advertised over mDNS. Binding to it
+/// fails when the machine has another host name.
+pub const LEGACY_LISTEN_HOST: &str = "last-snow.local";
+pub const DEFAULT_LISTEN_PORT: u16 = 7000;
+/// Most clients connected at once to each of the TCP and WebSocket listeners.
+const MAX_CONNECTIONS: usize = 16;
+/// Connections that send nothing for this long are closed, so dead clients
+/// don't keep their slot.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How often the TCP and WebSocket listeners check for connections and a new
+/// listen host.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Addresses this program only sends, as replies or announcements. They are
+/// never answered, so two kiosks or a device that echoes can't keep answering
+/// each other.
+const REPLY_ADDRESSES: &[&str] = &[
+    "/ok",
+    "/error",
+    "/trash/confirm",
+    "/heartbeat",
+    "/restart",
+    "/manifest",
+    "/new_row",
+    "/test_row",
+];
+
+pub fn is_reply(addr: &str) -> bool {
+    REPLY_ADDRESSES.contains(&addr)
+}
+
+/// Where the listener binds. A host name, an IPv4 or an IPv6 address, i.e.
+/// `0.0.0.0` or `::` for all interfaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenAddress {
+    pub host: String,
+    pub port: u16,
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl ListenAddress {
+    fn host(&self) -> &str {
+        self.host.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    pub fn bind(&self) -> Result<UdpSocket> {
+        Ok(UdpSocket::bind((self.host(), self.port))?)
+    }
+
+    pub fn bind_tcp(&self) -> Result<TcpListener> {
+        Ok(TcpListener::bind((self.host(), self.port))?)
+    }
+
+    /// Binds with `bind`, falling back to `127.0.0.1` on the same port.
+    pub fn bind_or_loopback<T>(&self, bind: impl Fn(&Self) -> Result<T>) -> Result<T> {
+        bind(self).or_else(|e| {
+            log::warn!("Error binding {}: {}, using 127.0.0.1", self, e);
+            bind(&Self {
+                host: "127.0.0.1".to_string(),
+                port: self.port,
+            })
+        })
+    }
+}
+
+static LISTEN_ADDRESS: Mutex<Option<ListenAddress>> = Mutex::new(None);
+static SEND_PORT: Mutex<u16> = Mutex::new(0);
+
+pub fn listen_address() -> ListenAddress {
+    LISTEN_ADDRESS
+        .lock()
+        .ok()
+        .and_then(|address| address.clone())
+        .unwrap_or_else(|| ListenAddress {
+            host: DEFAULT_LISTEN_HOST.to_string(),
+            port: DEFAULT_LISTEN_PORT,
+        })
+}
+
+/// Changes where the listeners bind, they rebind within a second. The TCP and
+/// WebSocket listeners only follow the host, they have ports of their own.
+pub fn set_listen_address(address: ListenAddress) {
+    if let Ok(mut current) = LISTEN_ADDRESS.lock() {
+        *current = Some(address);
+    }
+}
+
+/// Sets the source port for outgoing messages, 0 picks a free one for each message.
+pub fn set_send_port(port: u16) {
+    if let Ok(mut current) = SEND_PORT.lock() {
+        *current = port;
+    }
+}
+
+pub fn message(addr: &str, args: Vec<OscType>) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: addr.to_string(),
+        args,
+    })
+}
+
+/// Sends a single packet to `dest` (`ip:port` or `[ipv6]:port`) from the send port.
+pub fn send(dest: &str, packet: &OscPacket) -> Result<()> {
+    let dest_addr = dest
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::InvalidArgument(format!("Could not resolve {}", dest)))?;
+    let msg = rosc::encoder::encode(packet)?;
+
+    // Held while sending, so a fixed send port is only bound by one thread at a time
+    let port = SEND_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    let source: SocketAddr = if dest_addr.is_ipv6() {
+        (Ipv6Addr::UNSPECIFIED, *port).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, *port).into()
+    };
+    let socket = UdpSocket::bind(source)?;
+    log::debug!("Sending packet to {}: {:?}", dest, packet);
+    socket.send_to(&msg, dest_addr)?;
+    Ok(())
+}
+
+/// The connection a packet was received on.
+pub enum Transport<'a> {
+    Udp(&'a UdpSocket),
+    Tcp(&'a TcpStream),
+    WebSocket(&'a RefCell<WebSocket<TcpStream>>),
+}
+
+/// The sender of a received packet, so queries can be answered on the same socket
+/// or connection.
+pub struct Peer<'a> {
+    pub transport: Transport<'a>,
+    pub addr: SocketAddr,
+}
+
+impl Peer<'_> {
+    pub fn reply(&self, addr: &str, args: Vec<OscType>) -> Result<()> {
+        let msg = rosc::encoder::encode(&message(addr, args))?;
+        match self.transport {
+            Transport::Udp(socket) => {
+                socket.send_to(&msg, self.addr)?;
+            }
+            Transport::Tcp(mut stream) => stream.write_all(&slip::encode(&msg))?,
+            Transport::WebSocket(socket) => socket
+                .borrow_mut()
+                .send(tungstenite::Message::Binary(msg))?,
+        }
+        Ok(())
+    }
+}
+
+/// Handles a decoded packet, the same for every transport.
+pub type Handler = Arc<dyn Fn(OscPacket, &Peer) + Send + Sync>;
+
+/// Decodes a received packet and passes it to `handler`.
+pub fn dispatch(bytes: &[u8], peer: &Peer, handler: &Handler) {
+    log::info!(
+        "Received packet with size {} from: {}",
+        bytes.len(),
+        peer.addr
+    );
+    metrics().osc_received();
+    match rosc::decoder::decode_udp(bytes) {
+        Ok((_, packet)) => handler(packet, peer),
+        Err(e) => {
+            log::warn!("Error decoding packet from {}: {}", peer.addr, e);
+            metrics().osc_invalid();
+        }
+    }
+}
+
+/// Read timeouts are reported as `WouldBlock` on Unix and `TimedOut` on Windows.
+pub fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Frees a connection slot when the connection's thread ends.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Accepts connections on `listener` until the listen host changes, passing
+/// each to `handle` in a thread of its own, up to `MAX_CONNECTIONS` at once.
+fn accept_connections(
+    listener: &TcpListener,
+    host: &str,
+    transport: &'static str,
+    handler: &Handler,
+    handle: fn(TcpStream, &Handler) -> Result<()>,
+    connections: &Arc<AtomicUsize>,
+) {
+    loop {
+        if listen_address().host != host {
+            log::info!(
+                "Listen address changed, moving the OSC {} listener",
+                transport
+            );
+            return;
+        }
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if is_timeout(&e) => {
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                log::error!("Error accepting OSC {} connection: {}", transport, e);
+                continue;
+            }
+        };
+        if connections.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+            // Dropping the stream closes it
+            log::warn!(
+                "Refused OSC {} connection from {:?}, {} clients are connected",
+                transport,
+                stream.peer_addr(),
+                MAX_CONNECTIONS
+            );
+            continue;
+        }
+        // The listener doesn't block, so it can notice a new listen host
+        if let Err(e) = stream
+            .set_nonblocking(false)
+            .and_then(|()| stream.set_read_timeout(Some(IDLE_TIMEOUT)))
+            .and_then(|()| stream.set_write_timeout(Some(IDLE_TIMEOUT)))
+        {
+            log::error!("Error setting OSC {} timeouts: {}", transport, e);
+            continue;
+        }
+
+        connections.fetch_add(1, Ordering::SeqCst);
+        let slot = Slot(connections.clone());
+        let handler = handler.clone();
+        thread::spawn(move || {
+            let _slot = slot;
+            match handle(stream, &handler) {
+                Ok(()) => {}
+                Err(Error::Io(e)) if is_timeout(&e) => {
+                    log::info!("Closed idle OSC {} connection", transport);
+                }
+                Err(e) => log::warn!("OSC {} connection closed: {}", transport, e),
+            }
+        });
+    }
+}
+
+/// Listens on `port` of the listen host in a background thread and passes
+/// connections to `handle`. The listener is moved when the listen host
+/// changes, connections that are already open stay where they are.
+fn serve_connections(
+    port: u16,
+    transport: &'static str,
+    handler: Handler,
+    handle: fn(TcpStream, &Handler) -> Result<()>,
+) {
+    let connections = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || loop {
+        let address = ListenAddress {
+            host: listen_address().host,
+            port,
+        };
+        let listener = address
+            .bind_or_loopback(ListenAddress::bind_tcp)
+            .and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            });
+        match listener {
+            Ok(listener) => {
+                if let Ok(local) = listener.local_addr() {
+                    log::info!("Listening for OSC over {} on {}", transport, local);
+                }
+                accept_connections(
+                    &listener,
+                    &address.host,
+                    transport,
+                    &handler,
+                    handle,
+                    &connections,
+                );
+            }
+            Err(e) => {
+                log::error!(
+                    "Error starting OSC {} listener on {}: {}",
+                    transport,
+                    address,
+                    e
+                );
+                // Try again once the listen host is changed
+                while listen_address().host == address.host {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+            }
+        }
+    });
+}
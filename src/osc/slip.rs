@@ -0,0 +1,116 @@
+//! SLIP framing (RFC 1055) as used by OSC 1.1 over stream transports: each
+//! packet is escaped and sent between two `END` bytes.
+
+const END: u8 = 0xC0;
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// Frames larger than this are dropped, so a misbehaving client can't grow the buffer forever.
+const MAX_FRAME: usize = 64 * 1024;
+
+pub fn encode(packet: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(packet.len() + 2);
+    frame.push(END);
+    for &byte in packet {
+        match byte {
+            END => frame.extend([ESC, ESC_END]),
+            ESC => frame.extend([ESC, ESC_ESC]),
+            _ => frame.push(byte),
+        }
+    }
+    frame.push(END);
+    frame
+}
+
+/// Collects the packets in a stream that may split them across reads.
+#[derive(Default)]
+pub struct Decoder {
+    frame: Vec<u8>,
+    escaped: bool,
+    oversized: bool,
+}
+
+impl Decoder {
+    /// Returns the packets completed by `bytes`.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        for &byte in bytes {
+            if self.escaped {
+                self.escaped = false;
+                self.append(match byte {
+                    ESC_END => END,
+                    ESC_ESC => ESC,
+                    // Invalid escape, keep the byte as is
+                    _ => byte,
+                });
+                continue;
+            }
+            match byte {
+                END => {
+                    if self.oversized {
+                        log::warn!("Dropping SLIP frame larger than {} bytes", MAX_FRAME);
+                    } else if !self.frame.is_empty() {
+                        packets.push(std::mem::take(&mut self.frame));
+                    }
+                    self.frame.clear();
+                    self.oversized = false;
+                }
+                ESC => self.escaped = true,
+                _ => self.append(byte),
+            }
+        }
+        packets
+    }
+
+    fn append(&mut self, byte: u8) {
+        if self.frame.len() < MAX_FRAME {
+            self.frame.push(byte);
+        } else {
+            self.oversized = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_frame_split_across_reads() {
+        let frame = encode(b"/ping");
+        let mut decoder = Decoder::default();
+        assert!(decoder.push(&frame[..3]).is_empty());
+        assert_eq!(decoder.push(&frame[3..]), vec![b"/ping".to_vec()]);
+
+        // Two frames in one read, the second one split
+        let mut bytes = encode(b"/a");
+        bytes.extend(encode(b"/b"));
+        assert_eq!(decoder.push(&bytes[..6]), vec![b"/a".to_vec()]);
+        assert_eq!(decoder.push(&bytes[6..]), vec![b"/b".to_vec()]);
+    }
+
+    #[test]
+    fn unescapes_end_and_esc() {
+        let packet = [1, END, 2, ESC, 3];
+        let frame = encode(&packet);
+        assert_eq!(frame, vec![END, 1, ESC, ESC_END, 2, ESC, ESC_ESC, 3, END]);
+
+        // Split right after an escape
+        let mut decoder = Decoder::default();
+        assert!(decoder.push(&frame[..3]).is_empty());
+        assert_eq!(decoder.push(&frame[3..]), vec![packet.to_vec()]);
+    }
+
+    #[test]
+    fn drops_oversized_frame() {
+        let mut decoder = Decoder::default();
+        let oversized = encode(&vec![0; MAX_FRAME + 1]);
+        assert!(decoder.push(&oversized).is_empty());
+        // The next frame is decoded again
+        assert_eq!(decoder.push(&encode(b"/ok")), vec![b"/ok".to_vec()]);
+
+        let largest = vec![1; MAX_FRAME];
+        assert_eq!(decoder.push(&encode(&largest)), vec![largest]);
+    }
+}
@@ -0,0 +1,34 @@
+//! OSC 1.1 over TCP, with SLIP framed packets.
+
+use super::{dispatch, serve_connections, slip, Handler, Peer, Transport};
+use crate::error::Result;
+use std::io::Read;
+use std::net::TcpStream;
+
+fn handle_connection(stream: TcpStream, handler: &Handler) -> Result<()> {
+    let addr = stream.peer_addr()?;
+    log::info!("OSC TCP client {} connected", addr);
+    let peer = Peer {
+        transport: Transport::Tcp(&stream),
+        addr,
+    };
+
+    let mut decoder = slip::Decoder::default();
+    let mut buf = [0u8; 4096];
+    loop {
+        let size = (&stream).read(&mut buf)?;
+        if size == 0 {
+            log::info!("OSC TCP client {} disconnected", addr);
+            return Ok(());
+        }
+        for packet in decoder.push(&buf[..size]) {
+            dispatch(&packet, &peer, handler);
+        }
+    }
+}
+
+/// Accepts OSC connections on `port` of the listen host in a background thread,
+/// with a thread per client.
+pub fn serve(port: u16, handler: Handler) {
+    serve_connections(port, "TCP", handler, handle_connection);
+}
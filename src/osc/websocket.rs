@@ -0,0 +1,45 @@
+//! OSC over WebSocket, one packet per binary message.
+
+use super::{dispatch, serve_connections, Handler, Peer, Transport};
+use crate::error::{Error, Result};
+use crate::metrics::metrics;
+use std::cell::RefCell;
+use std::net::TcpStream;
+use tungstenite::Message;
+
+fn handle_connection(stream: TcpStream, handler: &Handler) -> Result<()> {
+    let addr = stream.peer_addr()?;
+    let socket = tungstenite::accept(stream).map_err(|e| Error::WebSocket(e.to_string()))?;
+    log::info!("OSC WebSocket client {} connected", addr);
+    let socket = RefCell::new(socket);
+    let peer = Peer {
+        transport: Transport::WebSocket(&socket),
+        addr,
+    };
+
+    loop {
+        let message = socket.borrow_mut().read();
+        match message {
+            Ok(Message::Binary(bytes)) => dispatch(&bytes, &peer, handler),
+            Ok(Message::Text(_)) => {
+                log::warn!("Ignoring text message from {}, OSC is sent as binary", addr);
+                metrics().osc_invalid();
+            }
+            // Pings and the closing handshake are answered by tungstenite
+            Ok(_) => {}
+            Err(tungstenite::Error::ConnectionClosed) => {
+                log::info!("OSC WebSocket client {} disconnected", addr);
+                return Ok(());
+            }
+            // Kept as an I/O error, so idle timeouts can be told apart
+            Err(tungstenite::Error::Io(e)) => return Err(e.into()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Accepts OSC WebSocket connections on `port` of the listen host in a
+/// background thread, with a thread per client.
+pub fn serve(port: u16, handler: Handler) {
+    serve_connections(port, "WebSocket", handler, handle_connection);
+}
@@ -0,0 +1,116 @@
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keeps the public and state dirs in `public` and `state` under `dir` instead
+/// of the user folders, so several instances can run on one machine.
+pub fn set_data_dir(dir: PathBuf) {
+    DATA_DIR.set(dir).unwrap_or_else(|dir| {
+        log::warn!("Data dir already set, ignoring {}", dir.display());
+    });
+}
+
+/// Directory for logs and other runtime state, kept out of the public share.
+pub fn state_dir() -> Result<PathBuf> {
+    let dir = match DATA_DIR.get() {
+        Some(data_dir) => data_dir.join("state"),
+        None => dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .ok_or(Error::StateDir)?
+            .join("last-snow"),
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// The shared folder with the CSV files, settings and language catalog.
+pub fn public_dir() -> Result<PathBuf> {
+    let Some(data_dir) = DATA_DIR.get() else {
+        return dirs::public_dir().ok_or(Error::PublicDir);
+    };
+    let dir = data_dir.join("public");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn unsafe_path(name: &str, reason: &str) -> Error {
+    log::warn!("Rejected path {}: {}", name, reason);
+    Error::UnsafePath(format!("{}: {}", name, reason))
+}
+
+/// Joins a file name received over OSC onto `base`, rejecting absolute paths,
+/// `.`, `..` and symlinks that lead outside of `base`.
+pub fn resolve(base: &Path, name: &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    if relative.as_os_str().is_empty() {
+        return Err(unsafe_path(name, "empty path"));
+    }
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => {}
+            Component::CurDir => return Err(unsafe_path(name, "contains .")),
+            Component::ParentDir => return Err(unsafe_path(name, "contains ..")),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_path(name, "absolute path"))
+            }
+        }
+    }
+
+    let path = base.join(relative);
+    let Ok(base) = base.canonicalize() else {
+        // Nothing can be linked from a folder that doesn't exist
+        return Ok(path);
+    };
+    // The path itself may not exist yet, so check the deepest part that does
+    let existing = path
+        .ancestors()
+        .find_map(|ancestor| ancestor.canonicalize().ok());
+    match existing {
+        Some(existing) if existing.starts_with(&base) => Ok(path),
+        _ => Err(unsafe_path(name, "leads outside of the folder")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("last-snow-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn resolves_names_in_folder() {
+        let base = temp_dir("resolve-inside");
+        assert_eq!(
+            resolve(&base, "sentences/EN.csv").unwrap(),
+            base.join("sentences/EN.csv")
+        );
+    }
+
+    #[test]
+    fn rejects_names_outside_folder() {
+        let base = temp_dir("resolve-outside");
+        for name in ["", ".", "./tmp.csv", "..", "../tmp.csv", "a/../../tmp.csv"] {
+            assert!(resolve(&base, name).is_err(), "{:?} was accepted", name);
+        }
+        assert!(resolve(&base, "/etc/passwd").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_folder() {
+        let base = temp_dir("resolve-symlink");
+        let outside = temp_dir("resolve-symlink-target");
+        std::os::unix::fs::symlink(&outside, base.join("link")).unwrap();
+        assert!(resolve(&base, "link/tmp.csv").is_err());
+        assert!(resolve(&base, "link").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! The question shown above the input, from `prompts` in settings. Prompts
+//! with dates are shown on those days, the others take turns.
+
+use crate::error::{Error, Result};
+use crate::store::Store;
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use std::collections::BTreeMap;
+
+#[derive(Debug, serde::Deserialize)]
+struct PromptConfig {
+    id: String,
+    text: BTreeMap<String, String>,
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    until: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub id: String,
+    /// The question per language code
+    text: BTreeMap<String, String>,
+    /// First and last day it is shown, both included
+    from: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+}
+
+impl Prompt {
+    fn scheduled(&self) -> bool {
+        self.from.is_some() || self.until.is_some()
+    }
+
+    fn shown_on(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.until.is_none_or(|until| date <= until)
+    }
+
+    /// The question in `language`, or in `fallback` when it has no translation.
+    pub fn text(&self, language: &str, fallback: &str) -> &str {
+        self.text
+            .get(language)
+            .or_else(|| self.text.get(fallback))
+            .or_else(|| self.text.values().next())
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+fn parse_date(value: Option<String>) -> Result<Option<NaiveDate>> {
+    value
+        .map(|value| {
+            NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                .map_err(|e| Error::InvalidArgument(format!("Invalid date {}: {}", value, e)))
+        })
+        .transpose()
+}
+
+#[derive(Debug, Default)]
+pub struct Prompts {
+    prompts: Vec<Prompt>,
+    /// How long each prompt without dates is shown before the next one
+    rotation_minutes: i64,
+}
+
+impl Prompts {
+    /// Reads `prompts` and `prompt_rotation_minutes`.
+    pub fn from_store(store: &Store) -> Result<Self> {
+        let configs: Vec<PromptConfig> = match store.get("prompts") {
+            Some(prompts) => serde_json::from_value(prompts.clone())?,
+            None => Vec::new(),
+        };
+        let prompts = configs
+            .into_iter()
+            .map(|config| {
+                Ok(Prompt {
+                    id: config.id,
+                    text: config.text,
+                    from: parse_date(config.from)?,
+                    until: parse_date(config.until)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let rotation_minutes = store
+            .get("prompt_rotation_minutes")
+            .and_then(|val| val.as_i64())
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(24 * 60);
+        Ok(Self {
+            prompts,
+            rotation_minutes,
+        })
+    }
+
+    fn current_at(&self, now: NaiveDateTime) -> Option<&Prompt> {
+        let date = now.date();
+        if let Some(prompt) = self
+            .prompts
+            .iter()
+            .find(|prompt| prompt.scheduled() && prompt.shown_on(date))
+        {
+            return Some(prompt);
+        }
+
+        let rotation: Vec<&Prompt> = self
+            .prompts
+            .iter()
+            .filter(|prompt| !prompt.scheduled())
+            .collect();
+        if rotation.is_empty() {
+            return None;
+        }
+        // Counted from the epoch in local time, so daily turns change at midnight
+        let epoch = NaiveDate::default().and_time(NaiveTime::default());
+        let turn = now.signed_duration_since(epoch).num_minutes() / self.rotation_minutes;
+        Some(rotation[turn.rem_euclid(rotation.len() as i64) as usize])
+    }
+
+    /// The prompt to show now, if any.
+    pub fn current(&self) -> Option<&Prompt> {
+        self.current_at(Local::now().naive_local())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::StoreBuilder;
+    use serde_json::{json, Value};
+
+    fn prompts(prompts: Value, rotation_minutes: i64) -> Prompts {
+        let mut store = StoreBuilder::new(".settings".into()).build();
+        store.insert("prompts".to_string(), prompts).unwrap();
+        store
+            .insert(
+                "prompt_rotation_minutes".to_string(),
+                json!(rotation_minutes),
+            )
+            .unwrap();
+        Prompts::from_store(&store).unwrap()
+    }
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn id_at(prompts: &Prompts, s: &str) -> Option<String> {
+        prompts.current_at(at(s)).map(|prompt| prompt.id.clone())
+    }
+
+    #[test]
+    fn dated_prompt_wins_over_rotation() {
+        let prompts = prompts(
+            json!([
+                {"id": "weather", "text": {"EN": "How is the weather?"}},
+                {"id": "opening", "text": {"EN": "Welcome!"}, "from": "2026-10-18", "until": "2026-10-19"},
+            ]),
+            24 * 60,
+        );
+        assert_eq!(
+            id_at(&prompts, "2026-10-17 23:59").as_deref(),
+            Some("weather")
+        );
+        assert_eq!(
+            id_at(&prompts, "2026-10-18 00:00").as_deref(),
+            Some("opening")
+        );
+        assert_eq!(
+            id_at(&prompts, "2026-10-19 23:59").as_deref(),
+            Some("opening")
+        );
+        assert_eq!(
+            id_at(&prompts, "2026-10-20 00:00").as_deref(),
+            Some("weather")
+        );
+    }
+
+    #[test]
+    fn rotates_at_turn_boundary() {
+        let prompts = prompts(
+            json!([
+                {"id": "a", "text": {"EN": "A"}},
+                {"id": "b", "text": {"EN": "B"}},
+            ]),
+            60,
+        );
+        let first = id_at(&prompts, "2026-10-18 10:00");
+        assert_eq!(id_at(&prompts, "2026-10-18 10:59"), first);
+        let second = id_at(&prompts, "2026-10-18 11:00");
+        assert_ne!(second, first);
+        assert_eq!(id_at(&prompts, "2026-10-18 12:00"), first);
+    }
+
+    #[test]
+    fn shows_nothing_without_prompts() {
+        let prompts = prompts(json!([]), 60);
+        assert!(id_at(&prompts, "2026-10-18 10:00").is_none());
+    }
+
+    #[test]
+    fn falls_back_to_other_languages() {
+        let prompts = prompts(
+            json!([{"id": "a", "text": {"EN": "Hello", "PL": "Cześć"}}]),
+            60,
+        );
+        let prompt = prompts.current_at(at("2026-10-18 10:00")).unwrap();
+        assert_eq!(prompt.text("PL", "EN"), "Cześć");
+        assert_eq!(prompt.text("UK", "EN"), "Hello");
+        assert_eq!(prompt.text("UK", "DE"), "Hello");
+    }
+}
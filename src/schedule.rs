@@ -0,0 +1,282 @@
+//! Opening hours from settings. Outside them the kiosk shows a closed screen,
+//! and during the test window submissions are kept apart from the real ones.
+
+use crate::error::{Error, Result};
+use crate::store::Store;
+use chrono::{Datelike, Days, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// How many days ahead the next opening is looked for.
+const LOOKAHEAD_DAYS: u64 = 14;
+
+/// A time range within a day. One ending at or before its start runs past
+/// midnight, i.e. `["20:00", "02:00"]`.
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Range {
+    fn overnight(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Whether `time` on the day of the range is in it.
+    fn contains(&self, time: NaiveTime) -> bool {
+        time >= self.start && (self.overnight() || time < self.end)
+    }
+
+    /// Whether `time` on the day after the range is in its overnight part.
+    fn contains_next_day(&self, time: NaiveTime) -> bool {
+        self.overnight() && time < self.end
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Open,
+    /// In the test window, submissions are tagged as test rows
+    Test,
+    Closed {
+        opens: Option<NaiveDateTime>,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct Schedule {
+    weekly: HashMap<Weekday, Vec<Range>>,
+    /// Days with other hours than their weekday, i.e. holidays
+    exceptions: HashMap<NaiveDate, Vec<Range>>,
+    test_window: Vec<Range>,
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|e| Error::InvalidArgument(format!("Invalid time {}: {}", value, e)))
+}
+
+/// Parses `[["10:00", "18:00"], ...]`.
+fn parse_ranges(value: &Value) -> Result<Vec<Range>> {
+    let ranges: Vec<[String; 2]> = serde_json::from_value(value.clone())?;
+    ranges
+        .iter()
+        .map(|[start, end]| {
+            Ok(Range {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            })
+        })
+        .collect()
+}
+
+impl Schedule {
+    /// Reads `opening_hours`, `holidays` and `test_window`. Without opening
+    /// hours the kiosk is open all day, except on holidays.
+    pub fn from_store(store: &Store) -> Result<Self> {
+        let mut schedule = Self::default();
+        if let Some(days) = store.get("opening_hours").and_then(|val| val.as_object()) {
+            for (day, ranges) in days {
+                let weekday: Weekday = day
+                    .parse()
+                    .map_err(|_| Error::InvalidArgument(format!("Invalid weekday {}", day)))?;
+                schedule.weekly.insert(weekday, parse_ranges(ranges)?);
+            }
+        }
+        if let Some(days) = store.get("holidays").and_then(|val| val.as_object()) {
+            for (date, ranges) in days {
+                let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|e| Error::InvalidArgument(format!("Invalid date {}: {}", date, e)))?;
+                schedule.exceptions.insert(date, parse_ranges(ranges)?);
+            }
+        }
+        if let Some(ranges) = store.get("test_window") {
+            schedule.test_window = parse_ranges(ranges)?;
+        }
+        Ok(schedule)
+    }
+
+    fn hours(&self, date: NaiveDate) -> Vec<Range> {
+        if let Some(hours) = self.exceptions.get(&date) {
+            return hours.clone();
+        }
+        if self.weekly.is_empty() {
+            // Midnight to midnight
+            return vec![Range {
+                start: NaiveTime::default(),
+                end: NaiveTime::default(),
+            }];
+        }
+        self.weekly
+            .get(&date.weekday())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn is_open(&self, now: NaiveDateTime) -> bool {
+        let (date, time) = (now.date(), now.time());
+        self.hours(date).iter().any(|range| range.contains(time))
+            || date.pred_opt().is_some_and(|yesterday| {
+                self.hours(yesterday)
+                    .iter()
+                    .any(|range| range.contains_next_day(time))
+            })
+    }
+
+    fn in_test_window(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        self.test_window
+            .iter()
+            .any(|range| range.contains(time) || range.contains_next_day(time))
+    }
+
+    /// The start of the next opening hours after `now`.
+    fn next_opening(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=LOOKAHEAD_DAYS)
+            .filter_map(|days| now.date().checked_add_days(Days::new(days)))
+            .flat_map(|date| {
+                let mut starts: Vec<NaiveDateTime> = self
+                    .hours(date)
+                    .iter()
+                    .map(|range| date.and_time(range.start))
+                    .collect();
+                starts.sort();
+                starts
+            })
+            .find(|start| *start > now)
+    }
+
+    fn status_at(&self, now: NaiveDateTime) -> Status {
+        if self.in_test_window(now) {
+            Status::Test
+        } else if self.is_open(now) {
+            Status::Open
+        } else {
+            Status::Closed {
+                opens: self.next_opening(now),
+            }
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status_at(Local::now().naive_local())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::StoreBuilder;
+    use serde_json::json;
+
+    fn schedule(settings: Value) -> Schedule {
+        let mut store = StoreBuilder::new(".settings".into()).build();
+        for (key, value) in settings.as_object().unwrap() {
+            store.insert(key.clone(), value.clone()).unwrap();
+        }
+        Schedule::from_store(&store).unwrap()
+    }
+
+    // 2026-10-19 is a Monday
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn stays_open_past_midnight() {
+        let schedule = schedule(json!({
+            "opening_hours": {"mon": [["20:00", "02:00"]]},
+        }));
+        assert_eq!(
+            schedule.status_at(at("2026-10-19 19:59")),
+            Status::Closed {
+                opens: Some(at("2026-10-19 20:00"))
+            }
+        );
+        assert_eq!(schedule.status_at(at("2026-10-19 23:00")), Status::Open);
+        assert_eq!(schedule.status_at(at("2026-10-20 01:59")), Status::Open);
+        assert_eq!(
+            schedule.status_at(at("2026-10-20 02:00")),
+            Status::Closed {
+                opens: Some(at("2026-10-26 20:00"))
+            }
+        );
+    }
+
+    #[test]
+    fn holidays_replace_weekday_hours() {
+        let schedule = schedule(json!({
+            "opening_hours": {"mon": [["10:00", "18:00"]], "tue": [["10:00", "18:00"]]},
+            "holidays": {"2026-10-19": [], "2026-10-20": [["12:00", "14:00"]]},
+        }));
+        assert!(matches!(
+            schedule.status_at(at("2026-10-19 11:00")),
+            Status::Closed { .. }
+        ));
+        assert!(matches!(
+            schedule.status_at(at("2026-10-20 11:00")),
+            Status::Closed { .. }
+        ));
+        assert_eq!(schedule.status_at(at("2026-10-20 13:00")), Status::Open);
+        assert_eq!(schedule.status_at(at("2026-10-26 11:00")), Status::Open);
+    }
+
+    #[test]
+    fn test_window_takes_precedence() {
+        let schedule = schedule(json!({
+            "opening_hours": {"mon": [["10:00", "18:00"]]},
+            "test_window": [["09:00", "10:30"]],
+        }));
+        assert_eq!(schedule.status_at(at("2026-10-19 09:30")), Status::Test);
+        assert_eq!(schedule.status_at(at("2026-10-19 10:15")), Status::Test);
+        assert_eq!(schedule.status_at(at("2026-10-19 10:30")), Status::Open);
+        // Every day, also when closed
+        assert_eq!(schedule.status_at(at("2026-10-20 09:30")), Status::Test);
+    }
+
+    #[test]
+    fn finds_next_opening() {
+        let schedule = schedule(json!({
+            "opening_hours": {"mon": [["14:00", "18:00"], ["10:00", "12:00"]]},
+        }));
+        assert_eq!(
+            schedule.next_opening(at("2026-10-19 08:00")),
+            Some(at("2026-10-19 10:00"))
+        );
+        assert_eq!(
+            schedule.next_opening(at("2026-10-19 12:30")),
+            Some(at("2026-10-19 14:00"))
+        );
+        assert_eq!(
+            schedule.next_opening(at("2026-10-19 18:00")),
+            Some(at("2026-10-26 10:00"))
+        );
+
+        let closed = schedule_closed_for_weeks();
+        assert_eq!(closed.next_opening(at("2026-10-19 08:00")), None);
+    }
+
+    fn schedule_closed_for_weeks() -> Schedule {
+        let holidays: serde_json::Map<String, Value> = (0..=LOOKAHEAD_DAYS)
+            .map(|days| {
+                let date = at("2026-10-19 00:00").date() + Days::new(days);
+                (date.format("%Y-%m-%d").to_string(), json!([]))
+            })
+            .collect();
+        schedule(json!({
+            "opening_hours": {"mon": [["10:00", "18:00"]]},
+            "holidays": holidays,
+        }))
+    }
+
+    #[test]
+    fn open_all_day_without_hours() {
+        let schedule = schedule(json!({}));
+        assert_eq!(schedule.status_at(at("2026-10-19 03:00")), Status::Open);
+        assert_eq!(
+            schedule.next_opening(at("2026-10-19 03:00")),
+            Some(at("2026-10-20 00:00"))
+        );
+    }
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
+use crate::paths::public_dir;
 use serde_json::Value as JsonValue;
 use std::{
     collections::HashMap,
@@ -9,7 +10,6 @@ use std::{
     io::Write,
     path::PathBuf,
 };
-use dirs::public_dir;
 
 type SerializeFn =
     fn(&HashMap<String, JsonValue>) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>;
@@ -171,7 +171,7 @@ pub struct Store {
 impl Store {
     /// Update the store from the on-disk state
     pub fn load(&mut self) -> Result<(), String> {
-        let app_dir = public_dir().expect("failed to resolve public dir");
+        let app_dir = public_dir().map_err(|e| e.to_string())?;
         let store_path = app_dir.join(&self.path);
 
         let bytes = read(store_path).map_err(|e| e.to_string())?;
@@ -184,11 +184,12 @@ impl Store {
 
     /// Saves the store to disk
     pub fn save(&self) -> Result<(), String> {
-        let app_dir = public_dir().expect("failed to resolve public dir");
+        let app_dir = public_dir().map_err(|e| e.to_string())?;
 
         let store_path = app_dir.join(&self.path);
 
-        create_dir_all(store_path.parent().expect("invalid store path")).map_err(|e| e.to_string())?;
+        create_dir_all(store_path.parent().expect("invalid store path"))
+            .map_err(|e| e.to_string())?;
 
         let bytes = (self.serialize)(&self.cache).map_err(|e| e.to_string())?;
         let mut f = File::create(&store_path).map_err(|e| e.to_string())?;
@@ -256,6 +257,17 @@ impl Store {
     }
 }
 
+/// Loads the `.settings` store in the public dir, logging and continuing with
+/// the defaults if it can't be read. Fails if there is no public dir.
+pub fn load_settings() -> crate::error::Result<Store> {
+    public_dir()?;
+    let mut store = StoreBuilder::new(".settings".into()).build();
+    store.load().unwrap_or_else(|e| {
+        log::error!("Error loading store: {}", e);
+    });
+    Ok(store)
+}
+
 impl std::fmt::Debug for Store {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Store")
@@ -0,0 +1,188 @@
+//! Supervisor mode (`last-snow --supervise`): runs the UI as a child process and
+//! restarts it when it crashes, backing off while it keeps crashing.
+
+use crate::crash;
+use crate::error::Result;
+use crate::health;
+use crate::logging::{self, LogConfig};
+use crate::osc;
+use crate::paths::state_dir;
+use crate::store::{load_settings, StoreBuilder};
+use crate::systemd;
+use rosc::OscType;
+use std::env;
+use std::fs;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const FLAG: &str = "--supervise";
+const RESTARTS_VAR: &str = "LAST_SNOW_RESTARTS";
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A child that ran at least this long was not crash looping, so the backoff is reset.
+const STABLE_AFTER: Duration = Duration::from_secs(300);
+/// In the state dir, the UI process sends its systemd notifications here.
+const NOTIFY_SOCKET: &str = "supervisor.sock";
+
+/// When the UI process last sent `WATCHDOG=1`, `None` while none is running.
+static LAST_PING: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn set_last_ping(ping: Option<Instant>) {
+    *LAST_PING.lock().unwrap_or_else(|e| e.into_inner()) = ping;
+}
+
+/// Times the supervisor restarted this process, 0 when not supervised.
+pub fn restarts() -> u64 {
+    env::var(RESTARTS_VAR)
+        .ok()
+        .and_then(|restarts| restarts.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Sends `/restart` with the restart count and the reason to the heartbeat destinations.
+fn report_restart(restarts: u64, reason: &str) {
+    let store = match load_settings() {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!("Error sending /restart: {}", e);
+            return;
+        }
+    };
+
+    let packet = osc::message(
+        "/restart",
+        vec![
+            OscType::Int(restarts as i32),
+            OscType::String(reason.to_string()),
+        ],
+    );
+    for dest in health::destinations(&store) {
+        osc::send(&dest, &packet).unwrap_or_else(|e| {
+            log::error!("Error sending /restart to {}: {}", dest, e);
+        });
+    }
+}
+
+/// Passes the watchdog pings of the UI process on to systemd, so a hung UI
+/// gets the service restarted. Returns the socket the UI process notifies.
+fn forward_watchdog(interval: Duration) -> Result<PathBuf> {
+    let path = state_dir()?.join(NOTIFY_SOCKET);
+    fs::remove_file(&path).ok();
+    let socket = UnixDatagram::bind(&path)?;
+    thread::spawn(move || {
+        let mut buf = [0u8; 256];
+        while let Ok(size) = socket.recv(&mut buf) {
+            if buf[..size]
+                .split(|byte| *byte == b'\n')
+                .any(|line| line == b"WATCHDOG=1")
+            {
+                set_last_ping(Some(Instant::now()));
+            }
+        }
+    });
+
+    // The interval is half of WatchdogSec, after which systemd gives up on us
+    let timeout = interval * 2;
+    thread::spawn(move || loop {
+        let responding = match *LAST_PING.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some(ping) => ping.elapsed() < timeout,
+            // Restarting, the supervisor itself is fine
+            None => true,
+        };
+        if responding {
+            systemd::notify("WATCHDOG=1");
+        } else {
+            log::error!("UI process stopped pinging the watchdog");
+        }
+        thread::sleep(interval);
+    });
+    Ok(path)
+}
+
+/// Runs the UI process until it exits cleanly, restarting it whenever it doesn't.
+pub fn run() -> ! {
+    let mut store = StoreBuilder::new(".settings".into()).build();
+    let store_loaded = store.load();
+
+    logging::setup_console(&LogConfig::from_store(&store)).unwrap_or_else(|e| {
+        eprintln!("Error setting up logger: {}", e);
+    });
+
+    store_loaded.unwrap_or_else(|e| {
+        log::error!("Error loading store: {}", e);
+    });
+
+    let exe = env::current_exe().unwrap_or_else(|e| {
+        log::error!("Error getting the path of the executable: {}", e);
+        process::exit(1);
+    });
+    let args: Vec<String> = env::args().skip(1).filter(|arg| arg != FLAG).collect();
+
+    // systemd watches the supervisor, which only pings it while the UI
+    // process pings the supervisor
+    systemd::notify("READY=1");
+    let notify_socket = systemd::watchdog_interval().and_then(|interval| {
+        forward_watchdog(interval)
+            .map_err(|e| log::error!("Error watching the UI process: {}", e))
+            .ok()
+    });
+
+    let mut restarts = 0;
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let started = Instant::now();
+        let mut command = Command::new(&exe);
+        command
+            .args(&args)
+            .env(RESTARTS_VAR, restarts.to_string())
+            .env_remove("NOTIFY_SOCKET")
+            .env_remove("WATCHDOG_PID");
+        match &notify_socket {
+            Some(path) => command.env("NOTIFY_SOCKET", path),
+            None => command.env_remove("WATCHDOG_USEC"),
+        };
+        // The UI process gets until the first timeout to start pinging
+        set_last_ping(Some(Instant::now()));
+        let exited = command.spawn().and_then(|mut child| {
+            log::info!("Started UI process {}", child.id());
+            Ok((child.id(), child.wait()?))
+        });
+        set_last_ping(None);
+
+        let reason = match exited {
+            Ok((_, status)) if status.success() => {
+                log::info!("UI process exited, stopping");
+                process::exit(0);
+            }
+            Ok((pid, status)) => {
+                log::error!("UI process {} exited with {}", pid, status);
+                // A panic already wrote a report with the backtrace
+                if status.code() != Some(crash::PANIC_EXIT_CODE) {
+                    let details = format!("UI process exited with {}", status);
+                    match crash::write_report(pid, &details) {
+                        Ok(path) => log::error!("Crash report written to {}", path.display()),
+                        Err(e) => log::error!("Error writing crash report: {}", e),
+                    }
+                }
+                status.to_string()
+            }
+            Err(e) => {
+                log::error!("Error running UI process: {}", e);
+                e.to_string()
+            }
+        };
+
+        if started.elapsed() > STABLE_AFTER {
+            backoff = MIN_BACKOFF;
+        }
+        restarts += 1;
+        report_restart(restarts, &reason);
+        log::info!("Restarting UI process in {:?}", backoff);
+        thread::sleep(backoff);
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
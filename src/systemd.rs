@@ -0,0 +1,37 @@
+//! The systemd notify protocol, for `Type=notify` units with `WatchdogSec=`.
+
+use std::env;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// Sends a state such as `READY=1` or `WATCHDOG=1` to systemd. Does nothing
+/// when not started by systemd.
+pub fn notify(state: &str) {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let result = UnixDatagram::unbound().and_then(|socket| match path.strip_prefix('@') {
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)
+        }
+        None => socket.send_to(state.as_bytes(), &path),
+    });
+    if let Err(e) = result {
+        log::warn!("Error notifying systemd: {}", e);
+    }
+}
+
+/// How often to send `WATCHDOG=1`, half of the unit's `WatchdogSec=`.
+pub fn watchdog_interval() -> Option<Duration> {
+    // Only the process systemd expects the pings from should send them
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    Some(Duration::from_micros(usec / 2))
+}
@@ -0,0 +1,244 @@
+//! Removed CSV files are moved to `trash` in the public dir instead of being
+//! deleted, so a mistaken remove command can be undone. Each remove command
+//! gets its own folder named by time, keeping the paths the files had.
+
+use crate::error::{Error, Result};
+use crate::paths::resolve;
+use chrono::Local;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+const TRASH_DIR: &str = "trash";
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+static CONFIRM_TOKEN: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+fn trash_dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(TRASH_DIR)
+}
+
+/// Moves the given files in `base_dir` to a new folder in the trash. Files that
+/// don't exist are skipped.
+pub fn move_to_trash(base_dir: &Path, files: &[PathBuf]) -> Result<()> {
+    let batch = trash_dir(base_dir).join(Local::now().format("%Y%m%d-%H%M%S%.3f").to_string());
+    for from in files {
+        if !from.exists() {
+            log::info!("File {} does not exist", from.display());
+            continue;
+        }
+        let relative = from.strip_prefix(base_dir).map_err(|_| {
+            Error::UnsafePath(format!(
+                "{} is not in {}",
+                from.display(),
+                base_dir.display()
+            ))
+        })?;
+        let to = batch.join(relative);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        log::info!("Moving {} to {}", from.display(), to.display());
+        fs::rename(from, &to)?;
+    }
+    Ok(())
+}
+
+fn files_in(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files_in(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The files in the trash, as `<folder>/<original path>`, oldest first.
+pub fn list(base_dir: &Path) -> Result<Vec<String>> {
+    let dir = trash_dir(base_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    files_in(&dir, &mut files)?;
+    let mut names: Vec<String> = files
+        .iter()
+        .filter_map(|file| file.strip_prefix(&dir).ok())
+        .map(|name| name.to_string_lossy().replace('\\', "/"))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Moves a file listed by [`list`] back where it was removed from.
+pub fn restore(base_dir: &Path, name: &str) -> Result<()> {
+    let from = resolve(&trash_dir(base_dir), name)?;
+    if !from.is_file() {
+        return Err(Error::InvalidArgument(format!(
+            "{} is not in the trash",
+            name
+        )));
+    }
+    // The first component is the folder of the remove command
+    let in_batch: PathBuf = Path::new(name).components().skip(1).collect();
+    let in_batch = in_batch.to_string_lossy();
+    let to = resolve(base_dir, &in_batch)?;
+    if to.exists() {
+        return Err(Error::InvalidArgument(format!(
+            "{} already exists",
+            in_batch
+        )));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    log::info!("Restoring {} to {}", from.display(), to.display());
+    fs::rename(&from, &to)?;
+    Ok(())
+}
+
+/// A token that has to be sent back within a minute to empty the trash.
+pub fn confirm_token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    let token = format!("{:016x}", hasher.finish());
+    if let Ok(mut confirm_token) = CONFIRM_TOKEN.lock() {
+        *confirm_token = Some((token.clone(), Instant::now()));
+    }
+    token
+}
+
+/// Deletes everything in the trash if `token` is the last one from [`confirm_token`].
+pub fn empty(base_dir: &Path, token: &str) -> Result<()> {
+    let expected = CONFIRM_TOKEN
+        .lock()
+        .map_err(|_| Error::InvalidArgument("Confirm token unavailable".to_string()))?
+        .take();
+    let confirmed = expected.is_some_and(|(expected, created)| {
+        expected == token && created.elapsed() < CONFIRM_TIMEOUT
+    });
+    if !confirmed {
+        return Err(Error::InvalidArgument(
+            "Invalid or expired confirm token".to_string(),
+        ));
+    }
+
+    let dir = trash_dir(base_dir);
+    if dir.exists() {
+        log::info!("Emptying {}", dir.display());
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+/// Deletes the trash folders older than `retention`.
+pub fn prune(base_dir: &Path, retention: Duration) {
+    let Ok(entries) = fs::read_dir(trash_dir(base_dir)) else {
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let expired = path
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > retention);
+        if expired {
+            log::info!("Deleting {} from the trash", path.display());
+            fs::remove_dir_all(&path).unwrap_or_else(|e| {
+                log::error!("Error deleting {}: {}", path.display(), e);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("last-snow-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn restores_to_original_path() {
+        let dir = temp_dir("trash-restore");
+        let file = dir.join("sentences").join("a.csv");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "row\n").unwrap();
+
+        move_to_trash(&dir, std::slice::from_ref(&file)).unwrap();
+        assert!(!file.exists());
+        let names = list(&dir).unwrap();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].ends_with("/sentences/a.csv"));
+
+        restore(&dir, &names[0]).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "row\n");
+        assert!(list(&dir).unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn does_not_restore_over_existing_file() {
+        let dir = temp_dir("trash-overwrite");
+        let file = dir.join("tmp.csv");
+        fs::write(&file, "old\n").unwrap();
+        move_to_trash(&dir, std::slice::from_ref(&file)).unwrap();
+        fs::write(&file, "new\n").unwrap();
+
+        let names = list(&dir).unwrap();
+        assert!(restore(&dir, &names[0]).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new\n");
+        assert_eq!(list(&dir).unwrap(), names);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn empties_only_with_confirm_token() {
+        let dir = temp_dir("trash-empty");
+        let file = dir.join("tmp.csv");
+        fs::write(&file, "row\n").unwrap();
+        move_to_trash(&dir, &[file]).unwrap();
+
+        // A wrong token also uses up the right one
+        let token = confirm_token();
+        assert!(empty(&dir, "wrong").is_err());
+        assert!(empty(&dir, &token).is_err());
+
+        let token = confirm_token();
+        let expired = Instant::now().checked_sub(CONFIRM_TIMEOUT + Duration::from_secs(1));
+        if let Some(expired) = expired {
+            *CONFIRM_TOKEN.lock().unwrap() = Some((token.clone(), expired));
+            assert!(empty(&dir, &token).is_err());
+        }
+        assert_eq!(list(&dir).unwrap().len(), 1);
+
+        let token = confirm_token();
+        empty(&dir, &token).unwrap();
+        assert!(list(&dir).unwrap().is_empty());
+        assert!(!trash_dir(&dir).exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}